@@ -1,10 +1,50 @@
 use cosmwasm_std::{
-    Addr, entry_point, to_binary, BankMsg, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Uint128
+    attr, Addr, Attribute, entry_point, from_json, to_json_binary, to_json_vec, BankMsg, Binary, Coin, CosmosMsg, Decimal, Deps, DepsMut, DistributionMsg, Env, HexBinary, IbcMsg, IbcTimeout,
+    MessageInfo, Order, QuerierWrapper, Reply, Response, StakingMsg, StdError, StdResult, Storage, SubMsg, SubMsgResult, Timestamp, Uint128, WasmMsg
 };
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+use cw4::{Cw4Contract, MemberChangedHookMsg};
+use bech32::{ToBase32, Variant};
+use cw_storage_plus::Bound;
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
 
 use crate::error::ContractError;
-use crate::msg::{ConfigResponse, ExecuteMsg, InstantiateMsg, QueryMsg, GetOwnerResponse, GetBalanceResponse};
-use crate::state::{Config, CONFIG, BALANCES};
+use crate::msg::{
+    Asset, AssetInfo, ChannelResponse, ChannelsResponse, ChequeNonceResponse, ChequeSignDoc, ConfigResponse, Cw20WhitelistResponse, EscrowResponse, ExecuteMsg, HtlcResponse, IbcChannelsResponse,
+    IbcLifecycleComplete, IbcTransferResponse, InstantiateMsg, MigrateMsg, LedgerChannelsResponse, LedgerCounterpartiesResponse, LedgerCredit, LedgerPacket,
+    OracleQueryMsg, OraclePriceResponse, PairExecuteMsg, MilestoneMsg, MilestoneResponse, PubkeyResponse, QueryMsg, ReceiveMsg, RelayNonceResponse, RemoteBalanceResponse, SwapPairResponse,
+    RelaySignDoc, SudoMsg, GetOwnerResponse, GetBalanceResponse, DelegationResponse, StakingStateResponse, UnbondingWithdrawalsResponse,
+    ShareholderMsg, ShareholderResponse, SplitGroupResponse, SplitGroupsResponse, SplitGroupVersionResponse,
+    AccountHistoryResponse, JournalEntryResponse, OrderBy, AccountSummaryResponse, GlobalStatsResponse,
+    TransferRecordResponse, TransfersResponse, InvoiceResponse, InvoicesResponse, HoldResponse, HoldsResponse,
+    CampaignResponse, CampaignsResponse, PledgeResponse, PledgesResponse, StandingOrderResponse, StandingOrdersResponse
+};
+use crate::state::{
+    channels, journal, split_groups, CancelPolicy, Channel, ChannelStatus, ChannelVoucher, Cheque, Config, Escrow, Htlc, IbcTransfer, LedgerCounterparty, OracleConfig, PendingSwap, AccountStats, EntryKind, GlobalStats, JournalEntry, Shareholder, SplitGroup, SplitMember, SplitGroupRef, StakingConfig, UnbondingWithdrawal, HtlcStatus, Milestone, MilestoneStatus, CONFIG, BALANCES, CHEQUE_NONCES, CW20_BALANCES,
+    CW20_WHITELIST, ESCROWS, ESCROW_COUNT,
+    HTLCS, HTLC_COUNT, IBC_CHANNELS, IBC_TRANSFERS, LEDGER_CHANNELS, LEDGER_COUNTERPARTIES, LEDGER_ESCROW, LEDGER_VOUCHERS, PENDING_IBC_TRANSFER, PUBKEYS, RELAY_NONCES,
+    ORACLE, PENDING_SWAP, REMOTE_BALANCES, SWAP_PAIRS, CHANNEL_COUNT, DELEGATIONS, PENDING_REWARD_CLAIM, PENDING_WITHDRAWAL, REWARD_INDEX, REWARD_SNAPSHOTS, STAKING,
+    TOTAL_BALANCE, UNBONDING_WITHDRAWALS, FEE_PER_SHARE, SHAREHOLDERS, TOTAL_SHARES, SPLIT_GROUP_COUNT, GROUP_WEIGHTS, TRACKED_GROUPS,
+    SPLIT_GROUP_VERSIONS, JOURNAL_COUNT, ACCOUNT_STATS, GLOBAL_STATS, VOLUME,
+    transfers, TransferNote, TransferRecord, MAX_CHALLENGE_PERIOD, MAX_MEMO_LENGTH, MAX_REFERENCE_LENGTH, TRANSFER_COUNT, TRANSFER_TIP,
+    holds, invoices, pledges, standing_orders, StandingOrder, DEFAULT_KEEPER_BOUNTY, KEEPER_BOUNTY, MAX_PERIODS_PER_EXECUTION, MAX_STANDING_ORDER_INTERVAL, STANDING_ORDER_COUNT, Campaign, CampaignStatus, Pledge, CAMPAIGNS, CAMPAIGN_COUNT, Hold, Invoice, InvoiceStatus, HOLD_COUNT, IdempotencyRecord, ReceiptCredit, TransferReceipt, RECEIPTS, DEFAULT_IDEMPOTENCY_WINDOW, IDEMPOTENCY_EXPIRIES, IDEMPOTENCY_KEYS, IDEMPOTENCY_WINDOW, MAX_IDEMPOTENCY_KEY_LENGTH
+};
+
+// Human readable part of account addresses on the chain this contract is deployed to.
+const BECH32_PREFIX: &str = "sei";
+
+// Page sizes for paginated queries.
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 30;
+
+// Expired idempotency keys forgotten per keyed transfer.
+const IDEMPOTENCY_PRUNE_LIMIT: usize = 10;
+
+const IBC_TRANSFER_REPLY_ID: u64 = 1;
+const SWAP_REPLY_ID: u64 = 2;
+const REWARDS_REPLY_ID: u64 = 3;
+const WITHDRAW_REWARDS_REPLY_ID: u64 = 4;
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
@@ -30,9 +70,110 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::Transfer { address1, address2, amount } => execute_transfer(deps, env, info, address1, address2, amount),
+        ExecuteMsg::Transfer { address1, address2, amount, memo, reference, idempotency_key } => {
+            let note = TransferNote { memo, reference };
+            let params = to_json_vec(&(&address1, &address2, amount, &note))?;
+            execute_idempotent(deps, env, info, idempotency_key, &params, |deps, env, info| {
+                execute_transfer(deps, env, info, address1, address2, amount, note)
+            })
+        }
         ExecuteMsg::Withdraw { amount } => execute_withdraw(deps, env, info, amount),
-        ExecuteMsg::TransferWithTip { address1, address2, amount } => execute_transfer_with_tip(deps, env, info, address1, address2, amount),
+        ExecuteMsg::TransferWithTip { address1, address2, amount, memo, reference, idempotency_key } => {
+            let note = TransferNote { memo, reference };
+            let params = to_json_vec(&("transfer_with_tip", &address1, &address2, amount, &note))?;
+            execute_idempotent(deps, env, info, idempotency_key, &params, |deps, env, info| {
+                execute_transfer_with_tip(deps, env, info, address1, address2, amount, note)
+            })
+        }
+        ExecuteMsg::CreateEscrow { contractor, milestones, cancel_policy } => execute_create_escrow(deps, env, info, contractor, milestones, cancel_policy),
+        ExecuteMsg::ReleaseMilestone { id } => execute_release_milestone(deps, env, info, id),
+        ExecuteMsg::CancelEscrow { id } => execute_cancel_escrow(deps, env, info, id),
+        ExecuteMsg::LockHtlc { recipient, hash, expiry } => execute_lock_htlc(deps, env, info, recipient, hash, expiry),
+        ExecuteMsg::ClaimHtlc { id, preimage } => execute_claim_htlc(deps, env, info, id, preimage),
+        ExecuteMsg::RefundHtlc { id } => execute_refund_htlc(deps, env, info, id),
+        ExecuteMsg::RegisterPubkey { pubkey } => execute_register_pubkey(deps, env, info, pubkey),
+        ExecuteMsg::RedeemCheque { cheque, signature } => execute_redeem_cheque(deps, env, info, cheque, signature),
+        ExecuteMsg::TransferBalance { recipient, amount, memo, reference, idempotency_key } => {
+            let note = TransferNote { memo, reference };
+            let params = to_json_vec(&("transfer_balance", &recipient, amount, &note))?;
+            execute_idempotent(deps, env, info, idempotency_key, &params, |deps, env, info| {
+                execute_transfer_balance(deps, env, info, recipient, amount, note)
+            })
+        }
+        ExecuteMsg::Relay { msg, signer_pubkey, signature, nonce } => execute_relay(deps, env, info, *msg, signer_pubkey, signature, nonce),
+        ExecuteMsg::OpenChannel { payee, challenge_period } => execute_open_channel(deps, env, info, payee, challenge_period),
+        ExecuteMsg::CloseChannel { id, amount, signature } => execute_close_channel(deps, env, info, id, amount, signature),
+        ExecuteMsg::RequestChannelClose { id } => execute_request_channel_close(deps, env, info, id),
+        ExecuteMsg::SettleChannel { id } => execute_settle_channel(deps, env, info, id),
+        ExecuteMsg::Receive(msg) => execute_receive(deps, env, info, msg),
+        ExecuteMsg::WithdrawCw20 { token, amount } => execute_withdraw_cw20(deps, env, info, token, amount),
+        ExecuteMsg::UpdateCw20Whitelist { add, remove } => execute_update_cw20_whitelist(deps, env, info, add, remove),
+        ExecuteMsg::WithdrawIbc { channel_id, remote_address, amount, timeout } => {
+            execute_withdraw_ibc(deps, env, info, channel_id, remote_address, amount, timeout)
+        }
+        ExecuteMsg::UpdateIbcChannels { add, remove } => execute_update_ibc_channels(deps, env, info, add, remove),
+        ExecuteMsg::IbcHookTransfer { channel, original_sender, address1, address2, memo, reference } => {
+            execute_ibc_hook_transfer(deps, env, info, channel, original_sender, address1, address2, TransferNote { memo, reference })
+        }
+        ExecuteMsg::UpdateLedgerCounterparties { add, remove } => execute_update_ledger_counterparties(deps, env, info, add, remove),
+        ExecuteMsg::SendLedgerCredits { channel_id, credits, timeout } => execute_send_ledger_credits(deps, env, info, channel_id, credits, timeout),
+        ExecuteMsg::ReturnLedgerCredits { channel_id, credits, timeout } => execute_return_ledger_credits(deps, env, info, channel_id, credits, timeout),
+        ExecuteMsg::QueryRemoteBalance { channel_id, address, timeout } => execute_query_remote_balance(deps, env, info, channel_id, address, timeout),
+        ExecuteMsg::WithdrawAndSwap { amount, denom, ask_denom, min_out } => execute_withdraw_and_swap(deps, env, info, amount, denom, ask_denom, min_out),
+        ExecuteMsg::UpdateSwapPair { offer_denom, ask_denom, pair } => execute_update_swap_pair(deps, env, info, offer_denom, ask_denom, pair),
+        ExecuteMsg::TransferUsd { address1, address2, usd_amount, belief_price, max_slippage, memo, reference, idempotency_key } => {
+            let note = TransferNote { memo, reference };
+            let params = to_json_vec(&("transfer_usd", &address1, &address2, usd_amount, belief_price, max_slippage, &note))?;
+            execute_idempotent(deps, env, info, idempotency_key, &params, |deps, env, info| {
+                execute_transfer_usd(deps, env, info, address1, address2, usd_amount, belief_price, max_slippage, note)
+            })
+        }
+        ExecuteMsg::TransferUsdWithTip { address1, address2, usd_amount, belief_price, max_slippage, memo, reference, idempotency_key } => {
+            let note = TransferNote { memo, reference };
+            let params = to_json_vec(&("transfer_usd_with_tip", &address1, &address2, usd_amount, belief_price, max_slippage, &note))?;
+            execute_idempotent(deps, env, info, idempotency_key, &params, |deps, env, info| {
+                execute_transfer_usd_with_tip(deps, env, info, address1, address2, usd_amount, belief_price, max_slippage, note)
+            })
+        }
+        ExecuteMsg::UpdateOracle { oracle, max_price_age } => execute_update_oracle(deps, env, info, oracle, max_price_age),
+        ExecuteMsg::UpdateStaking { validators, buffer_ratio, unbonding_period } => {
+            execute_update_staking(deps, env, info, validators, buffer_ratio, unbonding_period)
+        }
+        ExecuteMsg::Rebalance {} => execute_rebalance(deps, env, info),
+        ExecuteMsg::ClaimWithdrawal {} => execute_claim_withdrawal(deps, env, info),
+        ExecuteMsg::UpdateShareholders { shareholders } => execute_update_shareholders(deps, env, info, shareholders),
+        ExecuteMsg::ClaimFees {} => execute_claim_fees(deps, env, info),
+        ExecuteMsg::TransferToGroup { group_contract, at_height, memo, reference } => {
+            execute_transfer_to_group(deps, env, info, group_contract, at_height, TransferNote { memo, reference })
+        }
+        ExecuteMsg::TrackGroup { group_contract } => execute_track_group(deps, env, info, group_contract),
+        ExecuteMsg::MemberChangedHook(hook) => execute_member_changed_hook(deps, env, info, hook),
+        ExecuteMsg::CreateSplitGroup { name, members } => execute_create_split_group(deps, env, info, name, members),
+        ExecuteMsg::UpdateSplitGroup { id, members } => execute_update_split_group(deps, env, info, id, members),
+        ExecuteMsg::TransferToSplitGroup { id, memo, reference } => {
+            execute_transfer_to_split_group(deps, env, info, id, TransferNote { memo, reference })
+        }
+        ExecuteMsg::UpdateIdempotencyWindow { blocks } => execute_update_idempotency_window(deps, env, info, blocks),
+        ExecuteMsg::CreateInvoice { id, amount, denom, payer, expiry, split } => {
+            let invoice = Invoice { payee: info.sender.clone(), payer, amount, denom, expiry, split, status: InvoiceStatus::Open, paid_by: None };
+            execute_create_invoice(deps, env, info, id, invoice)
+        }
+        ExecuteMsg::PayInvoice { payee, id } => execute_pay_invoice(deps, env, info, payee, id),
+        ExecuteMsg::CancelInvoice { id } => execute_cancel_invoice(deps, env, info, id),
+        ExecuteMsg::ExpireInvoice { payee, id } => execute_expire_invoice(deps, env, info, payee, id),
+        ExecuteMsg::AuthorizeHold { merchant, amount, expiry } => execute_authorize_hold(deps, env, info, merchant, amount, expiry),
+        ExecuteMsg::CaptureHold { id, amount } => execute_capture_hold(deps, env, info, id, amount),
+        ExecuteMsg::ReleaseHold { id } => execute_release_hold(deps, env, info, id),
+        ExecuteMsg::CreateCampaign { target, deadline, split } => execute_create_campaign(deps, env, info, target, deadline, split),
+        ExecuteMsg::Contribute { id } => execute_contribute(deps, env, info, id),
+        ExecuteMsg::FinalizeCampaign { id } => execute_finalize_campaign(deps, env, info, id),
+        ExecuteMsg::ClaimRefund { id } => execute_claim_refund(deps, env, info, id),
+        ExecuteMsg::CreateStandingOrder { recipients, amount, interval, end } => {
+            execute_create_standing_order(deps, env, info, recipients, amount, interval, end)
+        }
+        ExecuteMsg::CancelStandingOrder { id } => execute_cancel_standing_order(deps, env, info, id),
+        ExecuteMsg::ExecuteDue { ids } => execute_execute_due(deps, env, info, ids),
+        ExecuteMsg::UpdateKeeperBounty { bounty } => execute_update_keeper_bounty(deps, env, info, bounty),
     }
 }
 
@@ -60,6 +201,261 @@ pub fn assert_sent_sufficient_coin(
     Ok(())
 }
 
+// The usei ledger balance of `address`, including staking rewards accrued since its last balance change.
+pub(crate) fn balance_of(storage: &dyn Storage, address: &Addr) -> StdResult<Uint128> {
+    let balance = BALANCES.may_load(storage, address.clone())?.unwrap_or_default();
+    let index = REWARD_INDEX.may_load(storage)?.unwrap_or_default();
+    let snapshot = REWARD_SNAPSHOTS.may_load(storage, address.clone())?.unwrap_or_default();
+    Ok(balance.checked_add(balance * (index - snapshot))?)
+}
+
+// Like `balance_of`, but as of the start of block `height`.
+fn balance_at_height(storage: &dyn Storage, address: &Addr, height: u64) -> StdResult<Uint128> {
+    let balance = BALANCES.may_load_at_height(storage, address.clone(), height)?.unwrap_or_default();
+    let index = REWARD_INDEX.may_load_at_height(storage, height)?.unwrap_or_default();
+    let snapshot = REWARD_SNAPSHOTS.may_load_at_height(storage, address.clone(), height)?.unwrap_or_default();
+    Ok(balance.checked_add(balance * (index - snapshot))?)
+}
+
+// Stores a settled balance together with the reward index it was settled at.
+fn save_balance(storage: &mut dyn Storage, env: &Env, address: Addr, balance: Uint128) -> StdResult<()> {
+    if let Some(index) = REWARD_INDEX.may_load(storage)? {
+        REWARD_SNAPSHOTS.save(storage, address.clone(), &index, env.block.height)?;
+    }
+    BALANCES.save(storage, address, &balance, env.block.height)
+}
+
+// Credits `amount` usei to `address` in the ledger and records it in its journal.
+pub(crate) fn credit_balance(
+    storage: &mut dyn Storage,
+    env: &Env,
+    address: Addr,
+    amount: Uint128,
+    kind: EntryKind,
+    counterparty: Option<Addr>,
+) -> StdResult<Uint128> {
+    let balance = settle_rewards(storage, env, &address)?.checked_add(amount)?;
+    save_balance(storage, env, address.clone(), balance)?;
+    let total = TOTAL_BALANCE.may_load(storage)?.unwrap_or_default();
+    TOTAL_BALANCE.save(storage, &total.checked_add(amount)?, env.block.height)?;
+    record_entry(storage, env, address, kind, counterparty, amount, "usei")?;
+    Ok(balance)
+}
+
+// Debits `amount` usei from the ledger balance of `address`, which must not dip into funds held for merchants, and
+// records it in its journal.
+fn debit_balance(
+    storage: &mut dyn Storage,
+    env: &Env,
+    address: Addr,
+    amount: Uint128,
+    kind: EntryKind,
+    counterparty: Option<Addr>,
+) -> Result<Uint128, ContractError> {
+    let balance = settle_rewards(storage, env, &address)?;
+    let remaining = balance.checked_sub(amount).map_err(|_| ContractError::InsufficientBalance {})?;
+    if remaining < held_balance(storage, env, &address)? {
+        return Err(ContractError::FundsOnHold {});
+    }
+    save_balance(storage, env, address.clone(), remaining)?;
+    let total = TOTAL_BALANCE.may_load(storage)?.unwrap_or_default();
+    TOTAL_BALANCE.save(storage, &total.saturating_sub(amount), env.block.height)?;
+    record_entry(storage, env, address, kind, counterparty, amount, "usei")?;
+    Ok(remaining)
+}
+
+// Journals the staking rewards `address` picked up since its last balance change, which its next balance change
+// settles, and returns its balance including them.
+fn settle_rewards(storage: &mut dyn Storage, env: &Env, address: &Addr) -> StdResult<Uint128> {
+    let balance = balance_of(storage, address)?;
+    let settled = BALANCES.may_load(storage, address.clone())?.unwrap_or_default();
+    if balance > settled {
+        record_entry(storage, env, address.clone(), EntryKind::Reward, None, balance - settled, "usei")?;
+    }
+    Ok(balance)
+}
+
+// The part of the ledger balance of `address` reserved by unexpired holds.  Expired holds are deleted on the way, so
+// the holds scanned here are only ever the live ones and those that expired since the last look.
+fn held_balance(storage: &mut dyn Storage, env: &Env, address: &Addr) -> StdResult<Uint128> {
+    let (live, expired): (Vec<_>, Vec<_>) = holds().idx.account.prefix(address.clone())
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?
+        .into_iter()
+        .partition(|(_, hold)| hold.expiry > env.block.time);
+    for (id, _) in expired {
+        holds().remove(storage, id)?;
+    }
+    Ok(live.into_iter().map(|(_, hold)| hold.amount).sum())
+}
+
+// Appends an entry to the journal of `account`.  Only usei entries count towards its received and withdrawn totals.
+fn record_entry(
+    storage: &mut dyn Storage,
+    env: &Env,
+    account: Addr,
+    kind: EntryKind,
+    counterparty: Option<Addr>,
+    amount: Uint128,
+    denom: &str,
+) -> StdResult<u64> {
+    let id = JOURNAL_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    JOURNAL_COUNT.save(storage, &id)?;
+    let entry = JournalEntry {
+        account,
+        kind,
+        counterparty,
+        amount,
+        denom: denom.to_string(),
+        height: env.block.height,
+        time: env.block.time,
+    };
+    journal().save(storage, id, &entry)?;
+
+    let usei = if denom == "usei" { amount } else { Uint128::zero() };
+    match entry.kind {
+        EntryKind::TransferIn => update_account_stats(storage, entry.account, |stats| {
+            stats.total_received += usei;
+            stats.transfers_received += 1;
+        })?,
+        EntryKind::Payment => update_account_stats(storage, entry.account, |stats| stats.total_received += usei)?,
+        EntryKind::Withdraw => update_account_stats(storage, entry.account, |stats| stats.total_withdrawn += usei)?,
+        EntryKind::WithdrawReverted => update_account_stats(storage, entry.account, |stats| {
+            stats.total_withdrawn = stats.total_withdrawn.saturating_sub(usei);
+        })?,
+        EntryKind::Tip => update_global_stats(storage, |stats| stats.fee_revenue += amount)?,
+        EntryKind::TransferOut | EntryKind::TipReceived | EntryKind::FeeClaim | EntryKind::Refund
+            | EntryKind::LedgerSend | EntryKind::LedgerReturn | EntryKind::Reward => (),
+    }
+    Ok(id)
+}
+
+fn update_global_stats(storage: &mut dyn Storage, action: impl FnOnce(&mut GlobalStats)) -> StdResult<()> {
+    let mut stats = GLOBAL_STATS.may_load(storage)?.unwrap_or_default();
+    action(&mut stats);
+    GLOBAL_STATS.save(storage, &stats)
+}
+
+// Updates the lifetime counters of `address`, counting it as active the first time.
+fn update_account_stats(storage: &mut dyn Storage, address: Addr, action: impl FnOnce(&mut AccountStats)) -> StdResult<()> {
+    let mut stats = match ACCOUNT_STATS.may_load(storage, address.clone())? {
+        Some(stats) => stats,
+        None => {
+            update_global_stats(storage, |stats| stats.active_accounts += 1)?;
+            AccountStats::default()
+        }
+    };
+    action(&mut stats);
+    ACCOUNT_STATS.save(storage, address, &stats)
+}
+
+fn validate_note(note: &TransferNote) -> Result<(), ContractError> {
+    if note.memo.as_ref().is_some_and(|memo| memo.len() > MAX_MEMO_LENGTH) {
+        return Err(ContractError::MemoTooLong { max: MAX_MEMO_LENGTH });
+    }
+    if note.reference.as_ref().is_some_and(|r| r.is_empty() || r.len() > MAX_REFERENCE_LENGTH) {
+        return Err(ContractError::InvalidReference { max: MAX_REFERENCE_LENGTH });
+    }
+    Ok(())
+}
+
+// The transfer id and, when given, the memo and reference, as response attributes.
+fn transfer_attributes(id: u64, note: TransferNote) -> Vec<Attribute> {
+    let mut attributes = vec![attr("transfer_id", id.to_string())];
+    if let Some(memo) = note.memo {
+        attributes.push(attr("memo", memo));
+    }
+    if let Some(reference) = note.reference {
+        attributes.push(attr("reference", reference));
+    }
+    attributes
+}
+
+// Credits one recipient of a transfer from `sender` and records it in the recipient's journal.
+fn credit_recipient(storage: &mut dyn Storage, env: &Env, sender: &Addr, address: Addr, amount: Uint128) -> StdResult<ReceiptCredit> {
+    let balance = credit_balance(storage, env, address.clone(), amount, EntryKind::TransferIn, Some(sender.clone()))?;
+    Ok(ReceiptCredit { address, amount, balance })
+}
+
+// Stores the record of one transfer of `amount` by `sender` and counts it.  The recipients are counted by their
+// TransferIn journal entries.  The receipt returned has no fee, refund or sender balance yet.
+fn record_transfer(
+    storage: &mut dyn Storage,
+    env: &Env,
+    sender: Addr,
+    credits: Vec<ReceiptCredit>,
+    amount: Uint128,
+    denom: &str,
+    note: TransferNote,
+) -> StdResult<TransferReceipt> {
+    let id = TRANSFER_COUNT.may_load(storage)?.unwrap_or_default() + 1;
+    TRANSFER_COUNT.save(storage, &id)?;
+    let record = TransferRecord {
+        sender: sender.clone(),
+        recipients: credits.iter().map(|c| c.address.clone()).collect(),
+        amount,
+        denom: denom.to_string(),
+        note,
+        height: env.block.height,
+        time: env.block.time,
+    };
+    transfers().save(storage, id, &record)?;
+
+    update_account_stats(storage, sender, |stats| stats.transfers_sent += 1)?;
+    update_global_stats(storage, |stats| stats.transfer_count += 1)?;
+    VOLUME.update(storage, denom.to_string(), |v| -> StdResult<_> { Ok(v.unwrap_or_default().checked_add(amount)?) })?;
+    Ok(TransferReceipt {
+        transfer_id: id,
+        denom: denom.to_string(),
+        credits,
+        fee: Uint128::zero(),
+        refund: Uint128::zero(),
+        sender_balance: None,
+        split_group: None,
+    })
+}
+
+// Keeps the receipt for the Receipt query and serializes it as response data.
+fn save_receipt(storage: &mut dyn Storage, receipt: &TransferReceipt) -> StdResult<Binary> {
+    RECEIPTS.save(storage, receipt.transfer_id, receipt)?;
+    to_json_binary(receipt)
+}
+
+// Moves `amount` usei between two ledger balances and records it in both journals.
+fn transfer_between(
+    storage: &mut dyn Storage,
+    env: &Env,
+    from: Addr,
+    to: Addr,
+    amount: Uint128,
+    note: TransferNote,
+) -> Result<TransferReceipt, ContractError> {
+    let remaining = debit_balance(storage, env, from.clone(), amount, EntryKind::TransferOut, Some(to.clone()))?;
+    let credit = credit_recipient(storage, env, &from, to, amount)?;
+    let mut receipt = record_transfer(storage, env, from, vec![credit], amount, "usei", note)?;
+    receipt.sender_balance = Some(remaining);
+    Ok(receipt)
+}
+
+fn total_staked(storage: &dyn Storage) -> StdResult<Uint128> {
+    DELEGATIONS.range(storage, None, None, Order::Ascending)
+        .try_fold(Uint128::zero(), |acc, item| Ok(acc + item?.1))
+}
+
+// The part of the ledger's usei that is held by the contract rather than delegated.
+fn liquid_balance(storage: &dyn Storage) -> StdResult<Uint128> {
+    let total = TOTAL_BALANCE.may_load(storage)?.unwrap_or_default();
+    Ok(total.saturating_sub(total_staked(storage)?))
+}
+
+// Debits a withdrawal that is paid out of the contract right away, which the liquid buffer must be able to cover.
+fn debit_liquid_balance(storage: &mut dyn Storage, env: &Env, address: Addr, amount: Uint128) -> Result<Uint128, ContractError> {
+    if liquid_balance(storage)? < amount {
+        return Err(ContractError::InsufficientLiquidity {});
+    }
+    debit_balance(storage, env, address, amount, EntryKind::Withdraw, None)
+}
+
 // This util to dispense from Bank is directly copied from https://github.com/deus-labs/cw-contracts/blob/main/contracts/escrow/src/contract.rs#LL99C1-L108C2
 fn send_tokens(to_address: Addr, amount: Vec<Coin>, action: &str) -> Response {
     Response::new()
@@ -71,60 +467,257 @@ fn send_tokens(to_address: Addr, amount: Vec<Coin>, action: &str) -> Response {
         .add_attribute("to", to_address)
 }
 
+// Withdrawals are paid out of the liquid buffer.  When staking is enabled and the buffer is short, the rest is
+// undelegated and can be claimed with ClaimWithdrawal once the unbonding period is over.
 pub fn execute_withdraw(
-    deps: DepsMut,
-    _env: Env,
+    mut deps: DepsMut,
+    env: Env,
     info: MessageInfo,
     amount: Uint128,
 ) -> Result<Response, ContractError> {
-    let balance = BALANCES.may_load(deps.storage, info.sender.clone())?;
-
-    if balance.is_none() {
+    if balance_of(deps.storage, &info.sender)? < amount {
         return Err(ContractError::InsufficientBalanceForWithdraw {});
-    } else {
-        let checked_balance = balance.unwrap();
-        if checked_balance < amount {
-            return Err(ContractError::InsufficientBalanceForWithdraw {});
+    }
+
+    // Changing a delegation pays out its pending rewards, so they are claimed and distributed in `reply` first,
+    // and the withdrawal itself is made there.
+    let liquid = liquid_balance(deps.storage)?;
+    if amount > liquid && STAKING.may_load(deps.storage)?.is_some() {
+        let validators = validators_to_unbond(deps.storage, amount - liquid)?;
+        let claims = claim_rewards(deps.branch(), &env, validators, WITHDRAW_REWARDS_REPLY_ID)?;
+        if !claims.is_empty() {
+            PENDING_WITHDRAWAL.save(deps.storage, &(info.sender, amount))?;
+            return Ok(Response::new()
+                .add_submessages(claims)
+                .add_attribute("action", "withdraw"));
         }
     }
+    withdraw_from_ledger(deps.storage, &env, info.sender, amount)
+}
 
-    let withdraw_amount = |a: Option<Uint128>| -> StdResult<_> { Ok(a.unwrap_or_default().checked_sub(amount).unwrap()) };
+fn withdraw_from_ledger(storage: &mut dyn Storage, env: &Env, address: Addr, amount: Uint128) -> Result<Response, ContractError> {
+    let liquid = liquid_balance(storage)?;
+    debit_balance(storage, env, address.clone(), amount, EntryKind::Withdraw, None)?;
+    if amount <= liquid {
+        return Ok(send_tokens(address, vec![Coin {denom: "usei".to_string(), amount}], "withdraw"));
+    }
 
-    BALANCES.update(deps.storage, info.sender.clone(), withdraw_amount)?;
+    let shortfall = amount - liquid;
+    let staking = STAKING.may_load(storage)?.ok_or(ContractError::StakingNotConfigured {})?;
+    let undelegations = undelegate(storage, shortfall)?;
+    let withdrawal = UnbondingWithdrawal { amount: shortfall, release_at: env.block.time.plus_seconds(staking.unbonding_period) };
+    UNBONDING_WITHDRAWALS.update(storage, address.clone(), |w| -> StdResult<_> {
+        let mut withdrawals = w.unwrap_or_default();
+        withdrawals.push(withdrawal);
+        Ok(withdrawals)
+    })?;
 
-    return Ok(send_tokens(info.sender.clone(), vec![Coin {denom: "usei".to_string(), amount: amount}], "withdraw"));
+    let res = if liquid.is_zero() {
+        Response::new().add_attribute("action", "withdraw")
+    } else {
+        send_tokens(address, vec![Coin {denom: "usei".to_string(), amount: liquid}], "withdraw")
+    };
+    Ok(res.add_messages(undelegations).add_attribute("unbonding", shortfall))
 }
 
 
 pub fn execute_transfer(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     address1: Addr,
     address2: Addr,
     amount: Uint128,
+    note: TransferNote,
 ) -> Result<Response, ContractError> {
+    validate_note(&note)?;
     // Check that requestor has sufficient usei tokens.
-    assert_sent_sufficient_coin(&info.funds, Some(Coin{ denom: "usei".to_string(), amount: amount}))?;
+    assert_sent_sufficient_coin(&info.funds, Some(Coin{ denom: "usei".to_string(), amount}))?;
 
     // NOTE: It was not specified what to do if sender sends the request with too many tokens.
     //       A reasonable remediation is to credit the sender with the unusued tokens.
 
-    // lambda function taken from cw-storage-plus tests:  https://github.com/CosmWasm/cw-storage-plus/blob/main/src/map.rs#LL1181C1-L1182C1
-    let add_half_amount = |a: Option<Uint128>| -> StdResult<_> { Ok(a.unwrap_or_default().checked_add(amount.checked_div(2u128.into()).unwrap()).unwrap()) };
+    let half_amount = amount.checked_div(2u128.into()).unwrap();
+    let credits = vec![
+        credit_recipient(deps.storage, &env, &info.sender, address1, half_amount)?,
+        credit_recipient(deps.storage, &env, &info.sender, address2, half_amount)?,
+    ];
+    let receipt = record_transfer(deps.storage, &env, info.sender, credits, amount, "usei", note.clone())?;
 
-    BALANCES.update(deps.storage, address1, add_half_amount)?;
-    BALANCES.update(deps.storage, address2, add_half_amount)?;
+    Ok(Response::default()
+        .add_attributes(transfer_attributes(receipt.transfer_id, note))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
 
-    Ok(Response::default())
+// Runs `transfer` under the sender's idempotency `key`, if one is given.  While the key is remembered, a retry with the
+// same `params` refunds the funds sent and returns the attributes of the original transfer instead of paying again.
+fn execute_idempotent(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    key: Option<String>,
+    params: &[u8],
+    transfer: impl FnOnce(DepsMut, Env, MessageInfo) -> Result<Response, ContractError>,
+) -> Result<Response, ContractError> {
+    let key = match key {
+        Some(key) => key,
+        None => return transfer(deps, env, info),
+    };
+    if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+        return Err(ContractError::InvalidIdempotencyKey { max: MAX_IDEMPOTENCY_KEY_LENGTH });
+    }
+    prune_idempotency_keys(deps.storage, env.block.height)?;
+
+    let params = Binary::from(Sha256::digest(params).to_vec());
+    if let Some(record) = IDEMPOTENCY_KEYS.may_load(deps.storage, (info.sender.clone(), key.clone()))? {
+        if record.expires_at > env.block.height {
+            if record.params != params {
+                return Err(ContractError::IdempotencyKeyReused { key });
+            }
+            let original = transfers().load(deps.storage, record.transfer_id)?;
+            let mut res = Response::new()
+                .add_attributes(transfer_attributes(record.transfer_id, original.note))
+                .add_attribute("idempotent_replay", "true")
+                .set_data(to_json_binary(&RECEIPTS.load(deps.storage, record.transfer_id)?)?);
+            if !info.funds.is_empty() {
+                res = res.add_message(BankMsg::Send { to_address: info.sender.to_string(), amount: info.funds });
+            }
+            return Ok(res);
+        }
+        IDEMPOTENCY_EXPIRIES.remove(deps.storage, (record.expires_at, info.sender.clone(), key.clone()));
+    }
+
+    let res = transfer(deps.branch(), env.clone(), info.clone())?;
+    let receipt: TransferReceipt = from_json(res.data.as_ref().ok_or_else(|| StdError::generic_err("Transfer returned no receipt"))?)?;
+    let window = IDEMPOTENCY_WINDOW.may_load(deps.storage)?.unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW);
+    let record = IdempotencyRecord {
+        params,
+        transfer_id: receipt.transfer_id,
+        expires_at: env.block.height + window,
+    };
+    IDEMPOTENCY_KEYS.save(deps.storage, (info.sender.clone(), key.clone()), &record)?;
+    IDEMPOTENCY_EXPIRIES.save(deps.storage, (record.expires_at, info.sender, key), &true)?;
+    Ok(res)
+}
+
+// Forgets up to IDEMPOTENCY_PRUNE_LIMIT keys that have expired by `height`.
+fn prune_idempotency_keys(storage: &mut dyn Storage, height: u64) -> StdResult<()> {
+    let expired = IDEMPOTENCY_EXPIRIES.keys(storage, None, None, Order::Ascending)
+        .take(IDEMPOTENCY_PRUNE_LIMIT)
+        .filter(|item| item.as_ref().map_or(true, |(expires_at, _, _)| *expires_at <= height))
+        .collect::<StdResult<Vec<_>>>()?;
+    for (expires_at, sender, key) in expired {
+        IDEMPOTENCY_EXPIRIES.remove(storage, (expires_at, sender.clone(), key.clone()));
+        IDEMPOTENCY_KEYS.remove(storage, (sender, key));
+    }
+    Ok(())
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::Config {} => to_binary::<ConfigResponse>(&CONFIG.load(deps.storage)?.into()),
+        QueryMsg::Config {} => to_json_binary::<ConfigResponse>(&CONFIG.load(deps.storage)?.into()),
         QueryMsg::GetOwner {} => get_owner_resolver(deps, env),
         QueryMsg::GetBalance { address } => get_balance_resolver(deps, env, address),
+        QueryMsg::GetBalanceAtHeight { address, height } => get_balance_at_height_resolver(deps, env, address, height),
+        QueryMsg::TotalBalanceAtHeight { height } => get_total_balance_at_height_resolver(deps, env, height),
+        QueryMsg::Escrow { id } => get_escrow_resolver(deps, env, id),
+        QueryMsg::Milestone { escrow_id, index } => get_milestone_resolver(deps, env, escrow_id, index),
+        QueryMsg::Htlc { id } => get_htlc_resolver(deps, env, id),
+        QueryMsg::Pubkey { address } => get_pubkey_resolver(deps, env, address),
+        QueryMsg::ChequeNonce { payer, nonce } => get_cheque_nonce_resolver(deps, env, payer, nonce),
+        QueryMsg::RelayNonce { address } => get_relay_nonce_resolver(deps, env, address),
+        QueryMsg::Channel { id } => get_channel_resolver(deps, env, id),
+        QueryMsg::ChannelsByPayer { payer, start_after, limit } => get_channels_by_payer_resolver(deps, env, payer, start_after, limit),
+        QueryMsg::ChannelsByPayee { payee, start_after, limit } => get_channels_by_payee_resolver(deps, env, payee, start_after, limit),
+        QueryMsg::GetCw20Balance { token, address } => get_cw20_balance_resolver(deps, env, token, address),
+        QueryMsg::Cw20Whitelist {} => get_cw20_whitelist_resolver(deps, env),
+        QueryMsg::IbcChannels {} => get_ibc_channels_resolver(deps, env),
+        QueryMsg::IbcTransfer { channel_id, sequence } => get_ibc_transfer_resolver(deps, env, channel_id, sequence),
+        QueryMsg::LedgerChannels {} => get_ledger_channels_resolver(deps, env),
+        QueryMsg::LedgerCounterparties {} => get_ledger_counterparties_resolver(deps, env),
+        QueryMsg::LedgerEscrow { channel_id } => get_ledger_escrow_resolver(deps, env, channel_id),
+        QueryMsg::LedgerVouchers { channel_id, address } => get_ledger_vouchers_resolver(deps, env, channel_id, address),
+        QueryMsg::RemoteBalance { channel_id, address } => get_remote_balance_resolver(deps, env, channel_id, address),
+        QueryMsg::SwapPair { offer_denom, ask_denom } => get_swap_pair_resolver(deps, env, offer_denom, ask_denom),
+        QueryMsg::OracleConfig {} => to_json_binary(&ORACLE.may_load(deps.storage)?),
+        QueryMsg::StakingConfig {} => to_json_binary(&STAKING.may_load(deps.storage)?),
+        QueryMsg::StakingState {} => get_staking_state_resolver(deps, env),
+        QueryMsg::UnbondingWithdrawals { address } => get_unbonding_withdrawals_resolver(deps, env, address),
+        QueryMsg::Shareholder { address } => get_shareholder_resolver(deps, env, address),
+        QueryMsg::SplitGroup { id } => get_split_group_resolver(deps, env, id),
+        QueryMsg::SplitGroupsByAdmin { admin, start_after, limit } => get_split_groups_by_admin_resolver(deps, env, admin, start_after, limit),
+        QueryMsg::SplitGroupVersion { id, version } => get_split_group_version_resolver(deps, env, id, version),
+        QueryMsg::AccountHistory { address, start_after, limit, order } => get_account_history_resolver(deps, env, address, start_after, limit, order),
+        QueryMsg::AccountSummary { address } => get_account_summary_resolver(deps, env, address),
+        QueryMsg::GlobalStats {} => get_global_stats_resolver(deps, env),
+        QueryMsg::TransfersByReference { reference, start_after, limit } => {
+            get_transfers_by_reference_resolver(deps, env, reference, start_after, limit)
+        }
+        QueryMsg::IdempotencyWindow {} => {
+            to_json_binary(&IDEMPOTENCY_WINDOW.may_load(deps.storage)?.unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW))
+        }
+        QueryMsg::IdempotencyKey { sender, key } => get_idempotency_key_resolver(deps, env, sender, key),
+        QueryMsg::Receipt { id } => to_json_binary(&RECEIPTS.load(deps.storage, id)?),
+        QueryMsg::Invoice { payee, id } => get_invoice_resolver(deps, env, payee, id),
+        QueryMsg::InvoicesByPayee { payee, start_after, limit } => get_invoices_by_payee_resolver(deps, env, payee, start_after, limit),
+        QueryMsg::InvoicesByPayer { payer, start_after, limit } => get_invoices_by_payer_resolver(deps, env, payer, start_after, limit),
+        QueryMsg::InvoicesByStatus { status, start_after, limit } => get_invoices_by_status_resolver(deps, env, status, start_after, limit),
+        QueryMsg::Hold { id } => to_json_binary(&hold_response(id, holds().load(deps.storage, id)?)),
+        QueryMsg::HoldsByAccount { account, start_after, limit } => get_holds_by_account_resolver(deps, env, account, start_after, limit),
+        QueryMsg::HoldsByMerchant { merchant, start_after, limit } => get_holds_by_merchant_resolver(deps, env, merchant, start_after, limit),
+        QueryMsg::Campaign { id } => to_json_binary(&campaign_response(id, CAMPAIGNS.load(deps.storage, id)?)),
+        QueryMsg::Campaigns { start_after, limit } => get_campaigns_resolver(deps, env, start_after, limit),
+        QueryMsg::CampaignPledges { id, start_after, limit } => get_campaign_pledges_resolver(deps, env, id, start_after, limit),
+        QueryMsg::ContributorPledges { contributor, start_after, limit } => {
+            get_contributor_pledges_resolver(deps, env, contributor, start_after, limit)
+        }
+        QueryMsg::KeeperBounty {} => to_json_binary(&keeper_bounty(deps.storage)?),
+        QueryMsg::StandingOrder { id } => to_json_binary(&standing_order_response(id, standing_orders().load(deps.storage, id)?)),
+        QueryMsg::DueStandingOrders { limit } => get_due_standing_orders_resolver(deps, env, limit),
+    }
+}
+
+// Balances used to live in a plain Map under the same namespace.  Migrating records every balance in the change log
+// at the migration height and recomputes the total liabilities from them.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let addresses = BALANCES.keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut total = Uint128::zero();
+    for address in addresses {
+        let balance = balance_of(deps.storage, &address)?;
+        save_balance(deps.storage, &env, address, balance)?;
+        total = total.checked_add(balance).map_err(StdError::from)?;
+    }
+    TOTAL_BALANCE.save(deps.storage, &total, env.block.height)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "migrate")
+        .add_attribute("total_balance", total))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        IBC_TRANSFER_REPLY_ID => reply_ibc_transfer(deps, env, msg.result),
+        SWAP_REPLY_ID => reply_swap(deps, env),
+        REWARDS_REPLY_ID => reply_rewards(deps, env),
+        WITHDRAW_REWARDS_REPLY_ID => reply_withdraw_rewards(deps, env),
+        id => Err(ContractError::UnknownReplyId { id }),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn sudo(deps: DepsMut, env: Env, msg: SudoMsg) -> Result<Response, ContractError> {
+    match msg {
+        SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcAck { channel, sequence, success, .. }) => {
+            sudo_ibc_transfer_complete(deps, &env, channel, sequence, success)
+        }
+        SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout { channel, sequence }) => {
+            sudo_ibc_transfer_complete(deps, &env, channel, sequence, false)
+        }
     }
 }
 
@@ -132,46 +725,2727 @@ fn get_owner_resolver(deps: Deps, _env: Env) -> StdResult<Binary> {
     let config = CONFIG.load(deps.storage)?;
     let address = config.owner.to_string();
     let resp = GetOwnerResponse { address };
-    to_binary(&resp)
+    to_json_binary(&resp)
 }
 
 fn get_balance_resolver(deps: Deps, _env: Env, address: Addr) -> StdResult<Binary> {
-    let balance = BALANCES.may_load(deps.storage, address)?;
-    let checked_balance = if balance.is_some() {
-        balance.unwrap()
-    } else {
-        Uint128::from(0u128)
+    let balance = balance_of(deps.storage, &address)?;
+
+    let resp = GetBalanceResponse { balance };
+    to_json_binary(&resp)
+}
+
+fn get_balance_at_height_resolver(deps: Deps, _env: Env, address: Addr, height: u64) -> StdResult<Binary> {
+    let balance = balance_at_height(deps.storage, &address, height)?;
+    to_json_binary(&GetBalanceResponse { balance })
+}
+
+fn get_total_balance_at_height_resolver(deps: Deps, _env: Env, height: u64) -> StdResult<Binary> {
+    let balance = TOTAL_BALANCE.may_load_at_height(deps.storage, height)?.unwrap_or_default();
+    to_json_binary(&GetBalanceResponse { balance })
+}
+
+fn milestone_response(index: usize, milestone: Milestone) -> MilestoneResponse {
+    MilestoneResponse {
+        index: index as u32,
+        amount: milestone.amount,
+        approver: milestone.approver,
+        status: milestone.status,
+    }
+}
+
+fn get_escrow_resolver(deps: Deps, _env: Env, id: u64) -> StdResult<Binary> {
+    let escrow = ESCROWS.load(deps.storage, id)?;
+    let resp = EscrowResponse {
+        id,
+        payer: escrow.payer,
+        contractor: escrow.contractor,
+        cancel_policy: escrow.cancel_policy,
+        milestones: escrow.milestones.into_iter().enumerate().map(|(i, m)| milestone_response(i, m)).collect(),
     };
+    to_json_binary(&resp)
+}
 
-    let resp = GetBalanceResponse { balance: checked_balance };
-    to_binary(&resp)
+fn get_milestone_resolver(deps: Deps, _env: Env, escrow_id: u64, index: u32) -> StdResult<Binary> {
+    let escrow = ESCROWS.load(deps.storage, escrow_id)?;
+    let milestone = escrow.milestones.into_iter().nth(index as usize)
+        .ok_or_else(|| StdError::not_found("Milestone"))?;
+    to_json_binary(&milestone_response(index as usize, milestone))
 }
 
 
-// For a transfer with tip, a flat 10 "usei" is stored for the contract owner under the same BALANCES model.
+// For a transfer with tip, a flat TRANSFER_TIP "usei" is shared among the fee revenue shareholders.  Until there are any, it is
+// stored for the contract owner under the same BALANCES model.
 pub fn execute_transfer_with_tip(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     address1: Addr,
     address2: Addr,
     amount: Uint128,
+    note: TransferNote,
 ) -> Result<Response, ContractError> {
-    // We need to adjust the amount required by the flat rate of 10 usei.  Alternatively, we could reduce the amount of usei that gets sent to the other addresses.
-    let adjusted_amount = amount.checked_add(Uint128::from(10u128)).unwrap();
+    validate_note(&note)?;
+    // We need to adjust the amount required by the flat rate of TRANSFER_TIP usei.  Alternatively, we could reduce the amount of usei that gets sent to the other addresses.
+    let tip = Uint128::from(TRANSFER_TIP);
+    let adjusted_amount = amount.checked_add(tip).map_err(StdError::from)?;
     // Check that requestor has sufficient usei tokens.
     assert_sent_sufficient_coin(&info.funds, Some(Coin{ denom: "usei".to_string(), amount: adjusted_amount}))?;
 
-    // lambda function taken from cw-storage-plus tests:  https://github.com/CosmWasm/cw-storage-plus/blob/main/src/map.rs#LL1181C1-L1182C1
-    let add_half_amount = |a: Option<Uint128>| -> StdResult<_> { Ok(a.unwrap_or_default().checked_add(amount.checked_div(2u128.into()).unwrap()).unwrap()) };
+    let half_amount = amount.checked_div(2u128.into()).unwrap();
+    let credits = vec![
+        credit_recipient(deps.storage, &env, &info.sender, address1, half_amount)?,
+        credit_recipient(deps.storage, &env, &info.sender, address2, half_amount)?,
+    ];
+    let mut receipt = record_transfer(deps.storage, &env, info.sender.clone(), credits, amount, "usei", note.clone())?;
 
-    BALANCES.update(deps.storage, address1, add_half_amount)?;
-    BALANCES.update(deps.storage, address2, add_half_amount)?;
+    let owner = collect_fee(deps.storage, &env, tip)?;
+    record_entry(deps.storage, &env, info.sender, EntryKind::Tip, owner, tip, "usei")?;
+    receipt.fee = tip;
 
-    // Credit contract owner with the 10 usei tip.
-    let add_10_usei_tip = |a: Option<Uint128>| -> StdResult<_> { Ok(a.unwrap_or_default().checked_add(Uint128::from(10u128)).unwrap()) };
-    let config = CONFIG.load(deps.storage)?;
-    BALANCES.update(deps.storage, config.owner, add_10_usei_tip)?;
+    Ok(Response::default()
+        .add_attributes(transfer_attributes(receipt.transfer_id, note))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
 
-    Ok(Response::default())
+fn get_htlc_resolver(deps: Deps, _env: Env, id: u64) -> StdResult<Binary> {
+    let htlc = HTLCS.load(deps.storage, id)?;
+    let resp = HtlcResponse {
+        id,
+        sender: htlc.sender,
+        recipient: htlc.recipient,
+        hash: htlc.hash,
+        expiry: htlc.expiry,
+        amount: htlc.amount,
+        status: htlc.status,
+        preimage: htlc.preimage,
+    };
+    to_json_binary(&resp)
+}
+
+fn get_pubkey_resolver(deps: Deps, _env: Env, address: Addr) -> StdResult<Binary> {
+    let pubkey = PUBKEYS.may_load(deps.storage, address)?;
+    to_json_binary(&PubkeyResponse { pubkey })
+}
+
+fn get_cheque_nonce_resolver(deps: Deps, _env: Env, payer: Addr, nonce: u64) -> StdResult<Binary> {
+    let used = CHEQUE_NONCES.has(deps.storage, (payer, nonce));
+    to_json_binary(&ChequeNonceResponse { used })
+}
+
+fn get_relay_nonce_resolver(deps: Deps, _env: Env, address: Addr) -> StdResult<Binary> {
+    let nonce = RELAY_NONCES.may_load(deps.storage, address)?.unwrap_or_default();
+    to_json_binary(&RelayNonceResponse { nonce })
+}
+
+fn channel_response(id: u64, channel: Channel) -> ChannelResponse {
+    ChannelResponse {
+        id,
+        payer: channel.payer,
+        payee: channel.payee,
+        deposit: channel.deposit,
+        claimed: channel.claimed,
+        challenge_period: channel.challenge_period,
+        status: channel.status,
+    }
+}
+
+fn get_channel_resolver(deps: Deps, _env: Env, id: u64) -> StdResult<Binary> {
+    let channel = channels().load(deps.storage, id)?;
+    to_json_binary(&channel_response(id, channel))
+}
+
+fn get_channels_by_payer_resolver(deps: Deps, _env: Env, payer: Addr, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let channels = channels().idx.payer.prefix(payer)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, channel)| channel_response(id, channel)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&ChannelsResponse { channels })
+}
+
+fn get_channels_by_payee_resolver(deps: Deps, _env: Env, payee: Addr, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let channels = channels().idx.payee.prefix(payee)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, channel)| channel_response(id, channel)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&ChannelsResponse { channels })
+}
+
+fn get_cw20_balance_resolver(deps: Deps, _env: Env, token: Addr, address: Addr) -> StdResult<Binary> {
+    let balance = CW20_BALANCES.may_load(deps.storage, (token, address))?.unwrap_or_default();
+    to_json_binary(&GetBalanceResponse { balance })
+}
+
+fn get_cw20_whitelist_resolver(deps: Deps, _env: Env) -> StdResult<Binary> {
+    let tokens = CW20_WHITELIST.keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&Cw20WhitelistResponse { tokens })
+}
+
+fn get_ibc_channels_resolver(deps: Deps, _env: Env) -> StdResult<Binary> {
+    let channels = IBC_CHANNELS.keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&IbcChannelsResponse { channels })
+}
+
+fn get_ibc_transfer_resolver(deps: Deps, _env: Env, channel_id: String, sequence: u64) -> StdResult<Binary> {
+    let transfer = IBC_TRANSFERS.load(deps.storage, (channel_id, sequence))?;
+    to_json_binary(&IbcTransferResponse { sender: transfer.sender, amount: transfer.amount })
+}
+
+fn get_ledger_channels_resolver(deps: Deps, _env: Env) -> StdResult<Binary> {
+    let channels = LEDGER_CHANNELS.keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&LedgerChannelsResponse { channels })
+}
+
+fn get_ledger_counterparties_resolver(deps: Deps, _env: Env) -> StdResult<Binary> {
+    let counterparties = LEDGER_COUNTERPARTIES.keys(deps.storage, None, None, Order::Ascending)
+        .map(|key| key.map(|(connection_id, port_id)| LedgerCounterparty { connection_id, port_id }))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&LedgerCounterpartiesResponse { counterparties })
+}
+
+fn get_ledger_escrow_resolver(deps: Deps, _env: Env, channel_id: String) -> StdResult<Binary> {
+    let balance = LEDGER_ESCROW.may_load(deps.storage, channel_id)?.unwrap_or_default();
+    to_json_binary(&GetBalanceResponse { balance })
+}
+
+fn get_ledger_vouchers_resolver(deps: Deps, _env: Env, channel_id: String, address: Addr) -> StdResult<Binary> {
+    let balance = LEDGER_VOUCHERS.may_load(deps.storage, (channel_id, address))?.unwrap_or_default();
+    to_json_binary(&GetBalanceResponse { balance })
+}
+
+fn get_remote_balance_resolver(deps: Deps, _env: Env, channel_id: String, address: String) -> StdResult<Binary> {
+    let balance = REMOTE_BALANCES.may_load(deps.storage, (channel_id, address))?;
+    to_json_binary(&RemoteBalanceResponse { balance })
+}
+
+fn get_swap_pair_resolver(deps: Deps, _env: Env, offer_denom: String, ask_denom: String) -> StdResult<Binary> {
+    let pair = SWAP_PAIRS.may_load(deps.storage, (offer_denom, ask_denom))?;
+    to_json_binary(&SwapPairResponse { pair })
+}
+
+fn get_staking_state_resolver(deps: Deps, _env: Env) -> StdResult<Binary> {
+    let delegations = DELEGATIONS.range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(validator, amount)| DelegationResponse { validator, amount }))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&StakingStateResponse {
+        total_balance: TOTAL_BALANCE.may_load(deps.storage)?.unwrap_or_default(),
+        delegations,
+        reward_index: REWARD_INDEX.may_load(deps.storage)?.unwrap_or_default(),
+    })
+}
+
+fn get_shareholder_resolver(deps: Deps, _env: Env, address: Addr) -> StdResult<Binary> {
+    let shareholder = match SHAREHOLDERS.may_load(deps.storage, address)? {
+        Some(shareholder) => settle_fees(deps.storage, shareholder)?,
+        None => Shareholder { shares: Uint128::zero(), fee_per_share_snapshot: Decimal::zero(), unclaimed: Uint128::zero() },
+    };
+    to_json_binary(&ShareholderResponse {
+        shares: shareholder.shares,
+        total_shares: TOTAL_SHARES.may_load(deps.storage)?.unwrap_or_default(),
+        claimable: shareholder.unclaimed,
+    })
+}
+
+fn split_group_response(id: u64, group: SplitGroup) -> SplitGroupResponse {
+    SplitGroupResponse {
+        id,
+        name: group.name,
+        admin: group.admin,
+        members: group.members,
+        version: group.version,
+    }
+}
+
+fn get_split_group_resolver(deps: Deps, _env: Env, id: u64) -> StdResult<Binary> {
+    let group = split_groups().load(deps.storage, id)?;
+    to_json_binary(&split_group_response(id, group))
+}
+
+fn get_split_groups_by_admin_resolver(deps: Deps, _env: Env, admin: Addr, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let groups = split_groups().idx.admin.prefix(admin)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, group)| split_group_response(id, group)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&SplitGroupsResponse { groups })
+}
+
+fn get_split_group_version_resolver(deps: Deps, _env: Env, id: u64, version: u32) -> StdResult<Binary> {
+    let members = SPLIT_GROUP_VERSIONS.load(deps.storage, (id, version))?;
+    to_json_binary(&SplitGroupVersionResponse { members })
+}
+
+fn get_account_history_resolver(
+    deps: Deps,
+    _env: Env,
+    address: Addr,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+    order: Option<OrderBy>,
+) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let order: Order = order.unwrap_or(OrderBy::Descending).into();
+    let (min, max) = match order {
+        Order::Ascending => (start_after.map(Bound::exclusive), None),
+        Order::Descending => (None, start_after.map(Bound::exclusive)),
+    };
+    let entries = journal().idx.account.prefix(address)
+        .range(deps.storage, min, max, order)
+        .take(limit)
+        .map(|item| item.map(|(id, entry)| JournalEntryResponse {
+            id,
+            kind: entry.kind,
+            counterparty: entry.counterparty,
+            amount: entry.amount,
+            denom: entry.denom,
+            height: entry.height,
+            time: entry.time,
+        }))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&AccountHistoryResponse { entries })
+}
+
+fn get_account_summary_resolver(deps: Deps, _env: Env, address: Addr) -> StdResult<Binary> {
+    let stats = ACCOUNT_STATS.may_load(deps.storage, address.clone())?.unwrap_or_default();
+    to_json_binary(&AccountSummaryResponse {
+        balance: balance_of(deps.storage, &address)?,
+        total_received: stats.total_received,
+        total_withdrawn: stats.total_withdrawn,
+        transfers_sent: stats.transfers_sent,
+        transfers_received: stats.transfers_received,
+    })
+}
+
+fn get_global_stats_resolver(deps: Deps, _env: Env) -> StdResult<Binary> {
+    let stats = GLOBAL_STATS.may_load(deps.storage)?.unwrap_or_default();
+    let volume = VOLUME.range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(denom, amount)| Coin { denom, amount }))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&GlobalStatsResponse {
+        transfer_count: stats.transfer_count,
+        fee_revenue: stats.fee_revenue,
+        active_accounts: stats.active_accounts,
+        volume,
+    })
+}
+
+fn get_transfers_by_reference_resolver(
+    deps: Deps,
+    _env: Env,
+    reference: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    if reference.is_empty() {
+        return Err(StdError::generic_err("Reference must not be empty"));
+    }
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let transfers = transfers().idx.reference.prefix(reference)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, record)| TransferRecordResponse {
+            id,
+            sender: record.sender,
+            recipients: record.recipients,
+            amount: record.amount,
+            denom: record.denom,
+            memo: record.note.memo,
+            reference: record.note.reference,
+            height: record.height,
+            time: record.time,
+        }))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&TransfersResponse { transfers })
+}
+
+fn get_idempotency_key_resolver(deps: Deps, env: Env, sender: Addr, key: String) -> StdResult<Binary> {
+    let record = IDEMPOTENCY_KEYS.may_load(deps.storage, (sender, key))?
+        .filter(|record| record.expires_at > env.block.height);
+    to_json_binary(&record)
+}
+
+fn get_unbonding_withdrawals_resolver(deps: Deps, _env: Env, address: Addr) -> StdResult<Binary> {
+    let withdrawals = UNBONDING_WITHDRAWALS.may_load(deps.storage, address)?.unwrap_or_default();
+    to_json_binary(&UnbondingWithdrawalsResponse { withdrawals })
+}
+
+// Escrow agreements hold the payer's funds in the contract until each milestone is released, in order, into the contractor's balance.
+pub fn execute_create_escrow(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    contractor: Addr,
+    milestones: Vec<MilestoneMsg>,
+    cancel_policy: CancelPolicy,
+) -> Result<Response, ContractError> {
+    if milestones.is_empty() || milestones.iter().any(|m| m.amount.is_zero()) {
+        return Err(ContractError::InvalidMilestones {});
+    }
+
+    let total = milestones.iter().try_fold(Uint128::zero(), |acc, m| acc.checked_add(m.amount))
+        .map_err(StdError::from)?;
+    // The deposit must cover the milestones exactly, since nothing beyond their total is ever paid out.
+    let deposit = one_coin(&info)?;
+    if deposit.denom != "usei" {
+        return Err(ContractError::InvalidFunds {});
+    }
+    if deposit.amount < total {
+        return Err(ContractError::InsufficientFundsSend {});
+    }
+    if deposit.amount > total {
+        return Err(ContractError::InvalidFunds {});
+    }
+
+    let escrow = Escrow {
+        payer: info.sender.clone(),
+        contractor,
+        cancel_policy,
+        milestones: milestones.into_iter().map(|m| Milestone {
+            amount: m.amount,
+            approver: m.approver.unwrap_or_else(|| info.sender.clone()),
+            status: MilestoneStatus::Pending,
+        }).collect(),
+    };
+
+    let id = ESCROW_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    ESCROW_COUNT.save(deps.storage, &id)?;
+    ESCROWS.save(deps.storage, id, &escrow)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_escrow")
+        .add_attribute("escrow_id", id.to_string())
+        .add_attribute("total", total))
+}
+
+pub fn execute_release_milestone(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut escrow = ESCROWS.load(deps.storage, id)?;
+    let payer = escrow.payer.clone();
+
+    // Milestones are released strictly in order, so only the first pending one is eligible.
+    let (index, milestone) = escrow.milestones.iter_mut().enumerate()
+        .find(|(_, m)| m.status == MilestoneStatus::Pending)
+        .ok_or(ContractError::NoPendingMilestones {})?;
+    if info.sender != payer && info.sender != milestone.approver {
+        return Err(ContractError::Unauthorized {});
+    }
+    milestone.status = MilestoneStatus::Released;
+    let amount = milestone.amount;
+
+    credit_balance(deps.storage, &env, escrow.contractor.clone(), amount, EntryKind::Payment, Some(payer.clone()))?;
+    ESCROWS.save(deps.storage, id, &escrow)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "release_milestone")
+        .add_attribute("escrow_id", id.to_string())
+        .add_attribute("milestone", index.to_string())
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_cancel_escrow(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut escrow = ESCROWS.load(deps.storage, id)?;
+
+    let allowed = match escrow.cancel_policy {
+        CancelPolicy::Never {} => false,
+        CancelPolicy::Payer {} => info.sender == escrow.payer,
+        CancelPolicy::PayerAfterHeight { height } => info.sender == escrow.payer && env.block.height >= height,
+        CancelPolicy::Contractor {} => info.sender == escrow.contractor,
+    };
+    if !allowed {
+        return Err(ContractError::CancelNotAllowed {});
+    }
+
+    let mut refund = Uint128::zero();
+    for milestone in escrow.milestones.iter_mut().filter(|m| m.status == MilestoneStatus::Pending) {
+        milestone.status = MilestoneStatus::Cancelled;
+        refund = refund.checked_add(milestone.amount).map_err(StdError::from)?;
+    }
+    if refund.is_zero() {
+        return Err(ContractError::NoPendingMilestones {});
+    }
+
+    // The refund is credited to the payer's ledger balance and can be taken out with the usual Withdraw.
+    credit_balance(deps.storage, &env, escrow.payer.clone(), refund, EntryKind::Refund, None)?;
+    ESCROWS.save(deps.storage, id, &escrow)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_escrow")
+        .add_attribute("escrow_id", id.to_string())
+        .add_attribute("refund", refund))
+}
+
+// Returns the single non-zero coin sent with the request.
+fn one_coin(info: &MessageInfo) -> Result<Coin, ContractError> {
+    match info.funds.as_slice() {
+        [coin] if !coin.amount.is_zero() => Ok(coin.clone()),
+        _ => Err(ContractError::InvalidFunds {}),
+    }
+}
+
+// HTLCs allow this contract to act as one leg of a cross-chain atomic swap.
+pub fn execute_lock_htlc(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: Addr,
+    hash: HexBinary,
+    expiry: Timestamp,
+) -> Result<Response, ContractError> {
+    if hash.len() != 32 {
+        return Err(ContractError::InvalidHash {});
+    }
+    if expiry <= env.block.time {
+        return Err(ContractError::InvalidExpiry {});
+    }
+    let amount = one_coin(&info)?;
+
+    let htlc = Htlc {
+        sender: info.sender,
+        recipient,
+        hash: hash.clone(),
+        expiry,
+        amount: amount.clone(),
+        status: HtlcStatus::Locked,
+        preimage: None,
+    };
+
+    let id = HTLC_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    HTLC_COUNT.save(deps.storage, &id)?;
+    HTLCS.save(deps.storage, id, &htlc)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "lock_htlc")
+        .add_attribute("htlc_id", id.to_string())
+        .add_attribute("hash", hash.to_hex())
+        .add_attribute("amount", amount.to_string()))
+}
+
+pub fn execute_claim_htlc(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    id: u64,
+    preimage: HexBinary,
+) -> Result<Response, ContractError> {
+    let mut htlc = HTLCS.load(deps.storage, id)?;
+    if htlc.status != HtlcStatus::Locked {
+        return Err(ContractError::HtlcNotLocked {});
+    }
+    if env.block.time >= htlc.expiry {
+        return Err(ContractError::HtlcExpired {});
+    }
+    if Sha256::digest(preimage.as_slice())[..] != htlc.hash[..] {
+        return Err(ContractError::InvalidPreimage {});
+    }
+
+    htlc.status = HtlcStatus::Claimed;
+    htlc.preimage = Some(preimage.clone());
+    HTLCS.save(deps.storage, id, &htlc)?;
+
+    // usei is credited to the recipient's ledger balance, any other denom is sent directly.
+    let res = if htlc.amount.denom == "usei" {
+        credit_balance(deps.storage, &env, htlc.recipient.clone(), htlc.amount.amount, EntryKind::Payment, Some(htlc.sender.clone()))?;
+        Response::new().add_attribute("action", "claim_htlc")
+    } else {
+        send_tokens(htlc.recipient.clone(), vec![htlc.amount.clone()], "claim_htlc")
+    };
+
+    // The preimage is published so that a relayer can complete the counterparty leg of the swap.
+    Ok(res
+        .add_attribute("htlc_id", id.to_string())
+        .add_attribute("recipient", htlc.recipient)
+        .add_attribute("preimage", preimage.to_hex()))
+}
+
+pub fn execute_refund_htlc(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut htlc = HTLCS.load(deps.storage, id)?;
+    if htlc.status != HtlcStatus::Locked {
+        return Err(ContractError::HtlcNotLocked {});
+    }
+    if env.block.time < htlc.expiry {
+        return Err(ContractError::HtlcNotExpired {});
+    }
+
+    htlc.status = HtlcStatus::Refunded;
+    HTLCS.save(deps.storage, id, &htlc)?;
+
+    Ok(send_tokens(htlc.sender, vec![htlc.amount], "refund_htlc")
+        .add_attribute("htlc_id", id.to_string()))
+}
+
+pub fn execute_register_pubkey(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    pubkey: HexBinary,
+) -> Result<Response, ContractError> {
+    // Compressed keys are 33 bytes, uncompressed keys are 65 bytes.
+    if pubkey.len() != 33 && pubkey.len() != 65 {
+        return Err(ContractError::InvalidPubkey {});
+    }
+    PUBKEYS.save(deps.storage, info.sender.clone(), &pubkey)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "register_pubkey")
+        .add_attribute("address", info.sender))
+}
+
+// Cheques are payment promises signed off-chain by the payer and settled against the payer's ledger balance.
+pub fn execute_redeem_cheque(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    cheque: Cheque,
+    signature: HexBinary,
+) -> Result<Response, ContractError> {
+    if cheque.denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom: cheque.denom });
+    }
+    if env.block.time >= cheque.expiry {
+        return Err(ContractError::ChequeExpired {});
+    }
+    if CHEQUE_NONCES.has(deps.storage, (cheque.payer.clone(), cheque.nonce)) {
+        return Err(ContractError::NonceAlreadyUsed {});
+    }
+
+    let pubkey = PUBKEYS.may_load(deps.storage, cheque.payer.clone())?
+        .ok_or_else(|| ContractError::PubkeyNotRegistered { address: cheque.payer.to_string() })?;
+    // Binding the chain id and contract address keeps a cheque from being redeemed on another deployment.
+    let sign_doc = ChequeSignDoc {
+        chain_id: env.block.chain_id.clone(),
+        contract: env.contract.address.clone(),
+        cheque,
+    };
+    let message_hash = Sha256::digest(to_json_vec(&sign_doc)?);
+    let verified = deps.api.secp256k1_verify(&message_hash, &signature, &pubkey)
+        .map_err(|_| ContractError::InvalidSignature {})?;
+    if !verified {
+        return Err(ContractError::InvalidSignature {});
+    }
+
+    let cheque = sign_doc.cheque;
+    CHEQUE_NONCES.save(deps.storage, (cheque.payer.clone(), cheque.nonce), &true)?;
+    let receipt = transfer_between(deps.storage, &env, cheque.payer.clone(), cheque.payee.clone(), cheque.amount, TransferNote::default())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "redeem_cheque")
+        .add_attribute("payer", cheque.payer)
+        .add_attribute("payee", cheque.payee)
+        .add_attribute("nonce", cheque.nonce.to_string())
+        .add_attribute("amount", cheque.amount)
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+pub fn execute_transfer_balance(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipient: Addr,
+    amount: Uint128,
+    note: TransferNote,
+) -> Result<Response, ContractError> {
+    validate_note(&note)?;
+    let receipt = transfer_between(deps.storage, &env, info.sender.clone(), recipient.clone(), amount, note.clone())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_balance")
+        .add_attribute("from", info.sender)
+        .add_attribute("to", recipient)
+        .add_attribute("amount", amount)
+        .add_attributes(transfer_attributes(receipt.transfer_id, note))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+// Derives the account address of a compressed secp256k1 public key, as the Cosmos SDK does.
+pub fn pubkey_to_address(pubkey: &[u8]) -> Result<Addr, ContractError> {
+    if pubkey.len() != 33 {
+        return Err(ContractError::InvalidPubkey {});
+    }
+    let hash = Ripemd160::digest(Sha256::digest(pubkey));
+    let address = bech32::encode(BECH32_PREFIX, hash.to_base32(), Variant::Bech32)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    Ok(Addr::unchecked(address))
+}
+
+// Derives the intermediate account ibc-hooks executes wasm memos from, for a sender on the other end of `channel`.
+pub fn ibc_hooks_sender(channel: &str, original_sender: &str) -> Result<Addr, ContractError> {
+    let mut hasher = Sha256::new();
+    hasher.update(Sha256::digest(b"ibc-wasm-hook-intermediary"));
+    hasher.update(format!("{}/{}", channel, original_sender).as_bytes());
+    let address = bech32::encode(BECH32_PREFIX, hasher.finalize().to_base32(), Variant::Bech32)
+        .map_err(|e| StdError::generic_err(e.to_string()))?;
+    Ok(Addr::unchecked(address))
+}
+
+// Meta-transactions let a relayer pay the gas for an account holder who signed the message off-chain.
+pub fn execute_relay(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    msg: ExecuteMsg,
+    signer_pubkey: HexBinary,
+    signature: HexBinary,
+    nonce: u64,
+) -> Result<Response, ContractError> {
+    match msg {
+        ExecuteMsg::TransferBalance { .. }
+        | ExecuteMsg::Withdraw { .. }
+        | ExecuteMsg::WithdrawCw20 { .. }
+        | ExecuteMsg::ReleaseMilestone { .. }
+        | ExecuteMsg::CancelEscrow { .. }
+        | ExecuteMsg::AuthorizeHold { .. } => (),
+        _ => return Err(ContractError::RelayNotAllowed {}),
+    }
+
+    let signer = pubkey_to_address(&signer_pubkey)?;
+    let expected = RELAY_NONCES.may_load(deps.storage, signer.clone())?.unwrap_or_default();
+    if nonce != expected {
+        return Err(ContractError::InvalidRelayNonce { expected });
+    }
+
+    let sign_doc = RelaySignDoc {
+        chain_id: env.block.chain_id.clone(),
+        contract: env.contract.address.clone(),
+        nonce,
+        msg,
+    };
+    let message_hash = Sha256::digest(to_json_vec(&sign_doc)?);
+    let verified = deps.api.secp256k1_verify(&message_hash, &signature, &signer_pubkey)
+        .map_err(|_| ContractError::InvalidSignature {})?;
+    if !verified {
+        return Err(ContractError::InvalidSignature {});
+    }
+    RELAY_NONCES.save(deps.storage, signer.clone(), &(nonce + 1))?;
+
+    // The inner message runs exactly as if the signer had sent it without funds.
+    let signer_info = MessageInfo { sender: signer.clone(), funds: vec![] };
+    let res = execute(deps, env, signer_info, sign_doc.msg)?;
+    Ok(res
+        .add_attribute("relayed_for", signer)
+        .add_attribute("relay_nonce", nonce.to_string()))
+}
+
+// Payment channels let a payer stream micro-payments to a payee off-chain with signed cumulative vouchers.
+pub fn execute_open_channel(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    payee: Addr,
+    challenge_period: u64,
+) -> Result<Response, ContractError> {
+    let deposit = one_coin(&info)?;
+    if deposit.denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom: deposit.denom });
+    }
+    if challenge_period > MAX_CHALLENGE_PERIOD {
+        return Err(ContractError::InvalidChallengePeriod { max: MAX_CHALLENGE_PERIOD });
+    }
+    // Vouchers are verified against the key the payer has registered now, so rotating it later cannot void them.
+    let payer_pubkey = PUBKEYS.may_load(deps.storage, info.sender.clone())?
+        .ok_or_else(|| ContractError::PubkeyNotRegistered { address: info.sender.to_string() })?;
+
+    let channel = Channel {
+        payer: info.sender,
+        payee,
+        deposit: deposit.amount,
+        claimed: Uint128::zero(),
+        challenge_period,
+        status: ChannelStatus::Open,
+        payer_pubkey,
+    };
+
+    let id = CHANNEL_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    CHANNEL_COUNT.save(deps.storage, &id)?;
+    channels().save(deps.storage, id, &channel)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "open_channel")
+        .add_attribute("channel_id", id.to_string())
+        .add_attribute("deposit", deposit.amount))
+}
+
+pub fn execute_close_channel(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    amount: Uint128,
+    signature: HexBinary,
+) -> Result<Response, ContractError> {
+    let mut channel = channels().load(deps.storage, id)?;
+    if info.sender != channel.payee {
+        return Err(ContractError::Unauthorized {});
+    }
+    let settle_at = match channel.status {
+        ChannelStatus::Open => env.block.time.plus_seconds(channel.challenge_period),
+        ChannelStatus::Closing { settle_at } if env.block.time < settle_at => {
+            if amount <= channel.claimed {
+                return Err(ContractError::VoucherNotHigher {});
+            }
+            settle_at
+        }
+        ChannelStatus::Closing { .. } => return Err(ContractError::ChallengePeriodOver {}),
+        ChannelStatus::Settled => return Err(ContractError::ChannelNotOpen {}),
+    };
+    if amount > channel.deposit {
+        return Err(ContractError::VoucherExceedsDeposit {});
+    }
+
+    let voucher = ChannelVoucher {
+        chain_id: env.block.chain_id.clone(),
+        contract: env.contract.address.clone(),
+        channel_id: id,
+        amount,
+    };
+    let message_hash = Sha256::digest(to_json_vec(&voucher)?);
+    let verified = deps.api.secp256k1_verify(&message_hash, &signature, &channel.payer_pubkey)
+        .map_err(|_| ContractError::InvalidSignature {})?;
+    if !verified {
+        return Err(ContractError::InvalidSignature {});
+    }
+
+    channel.claimed = amount;
+    channel.status = ChannelStatus::Closing { settle_at };
+    channels().save(deps.storage, id, &channel)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "close_channel")
+        .add_attribute("channel_id", id.to_string())
+        .add_attribute("claimed", amount)
+        .add_attribute("settle_at", settle_at.to_string()))
+}
+
+pub fn execute_request_channel_close(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut channel = channels().load(deps.storage, id)?;
+    if info.sender != channel.payer {
+        return Err(ContractError::Unauthorized {});
+    }
+    if channel.status != ChannelStatus::Open {
+        return Err(ContractError::ChannelNotOpen {});
+    }
+
+    let settle_at = env.block.time.plus_seconds(channel.challenge_period);
+    channel.status = ChannelStatus::Closing { settle_at };
+    channels().save(deps.storage, id, &channel)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "request_channel_close")
+        .add_attribute("channel_id", id.to_string())
+        .add_attribute("settle_at", settle_at.to_string()))
+}
+
+pub fn execute_settle_channel(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut channel = channels().load(deps.storage, id)?;
+    match channel.status {
+        ChannelStatus::Closing { settle_at } if env.block.time >= settle_at => (),
+        ChannelStatus::Closing { .. } => return Err(ContractError::ChallengePeriodActive {}),
+        _ => return Err(ContractError::ChannelNotOpen {}),
+    }
+
+    // Both parties are settled into the ledger and use the usual Withdraw.
+    let refund = channel.deposit.checked_sub(channel.claimed).map_err(StdError::from)?;
+    if !channel.claimed.is_zero() {
+        credit_balance(deps.storage, &env, channel.payee.clone(), channel.claimed, EntryKind::Payment, Some(channel.payer.clone()))?;
+    }
+    if !refund.is_zero() {
+        credit_balance(deps.storage, &env, channel.payer.clone(), refund, EntryKind::Refund, None)?;
+    }
+    channel.status = ChannelStatus::Settled;
+    channels().save(deps.storage, id, &channel)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "settle_channel")
+        .add_attribute("channel_id", id.to_string())
+        .add_attribute("paid", channel.claimed)
+        .add_attribute("refund", refund))
+}
+
+// CW20 tokens arrive through the Receive hook, where info.sender is the token contract.
+pub fn execute_receive(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    wrapper: Cw20ReceiveMsg,
+) -> Result<Response, ContractError> {
+    let token = info.sender;
+    if !CW20_WHITELIST.has(deps.storage, token.clone()) {
+        return Err(ContractError::Cw20NotWhitelisted { token: token.to_string() });
+    }
+
+    let amount = wrapper.amount;
+    let receipt = match from_json(&wrapper.msg)? {
+        ReceiveMsg::Transfer { address1, address2 } => {
+            // Split the same way as the native Transfer.
+            let half = amount.checked_div(2u128.into()).map_err(StdError::from)?;
+            let add_half_amount = |a: Option<Uint128>| -> StdResult<_> { Ok(a.unwrap_or_default().checked_add(half)?) };
+            let sender = Addr::unchecked(&wrapper.sender);
+            let mut credits = vec![];
+            for address in [address1, address2] {
+                let balance = CW20_BALANCES.update(deps.storage, (token.clone(), address.clone()), add_half_amount)?;
+                record_entry(deps.storage, &env, address.clone(), EntryKind::TransferIn, Some(sender.clone()), half, token.as_str())?;
+                credits.push(ReceiptCredit { address, amount: half, balance });
+            }
+            record_transfer(deps.storage, &env, sender, credits, amount, token.as_str(), TransferNote::default())?
+        }
+    };
+
+    Ok(Response::new()
+        .add_attribute("action", "receive_cw20")
+        .add_attribute("token", token)
+        .add_attribute("from", wrapper.sender)
+        .add_attribute("amount", amount)
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+pub fn execute_withdraw_cw20(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token: Addr,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let key = (token.clone(), info.sender.clone());
+    let balance = CW20_BALANCES.may_load(deps.storage, key.clone())?.unwrap_or_default();
+    let remaining = balance.checked_sub(amount).map_err(|_| ContractError::InsufficientBalanceForWithdraw {})?;
+    CW20_BALANCES.save(deps.storage, key, &remaining)?;
+    record_entry(deps.storage, &env, info.sender.clone(), EntryKind::Withdraw, None, amount, token.as_str())?;
+
+    let transfer = WasmMsg::Execute {
+        contract_addr: token.to_string(),
+        msg: to_json_binary(&Cw20ExecuteMsg::Transfer { recipient: info.sender.to_string(), amount })?,
+        funds: vec![],
+    };
+    Ok(Response::new()
+        .add_message(transfer)
+        .add_attribute("action", "withdraw_cw20")
+        .add_attribute("token", token)
+        .add_attribute("to", info.sender)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_update_cw20_whitelist(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add: Vec<Addr>,
+    remove: Vec<Addr>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    for token in add {
+        let token = deps.api.addr_validate(token.as_str())?;
+        CW20_WHITELIST.save(deps.storage, token, &true)?;
+    }
+    // Removing a token only stops new deposits, existing balances can still be withdrawn.
+    for token in remove {
+        CW20_WHITELIST.remove(deps.storage, token);
+    }
+
+    Ok(Response::new().add_attribute("action", "update_cw20_whitelist"))
+}
+
+// Withdrawals to other chains go out as ICS-20 transfers.  A failed transfer is re-credited in `reply` or `sudo`.
+pub fn execute_withdraw_ibc(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    remote_address: String,
+    amount: Uint128,
+    timeout: u64,
+) -> Result<Response, ContractError> {
+    if !IBC_CHANNELS.has(deps.storage, channel_id.clone()) {
+        return Err(ContractError::IbcChannelNotAllowed { channel_id });
+    }
+    debit_liquid_balance(deps.storage, &env, info.sender.clone(), amount)
+        .map_err(|e| match e {
+            ContractError::InsufficientBalance {} => ContractError::InsufficientBalanceForWithdraw {},
+            e => e,
+        })?;
+    PENDING_IBC_TRANSFER.save(deps.storage, &IbcTransfer { sender: info.sender.clone(), amount })?;
+
+    let transfer = ibc_transfer_msg(&env, &channel_id, &remote_address, amount, env.block.time.plus_seconds(timeout));
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_always(transfer, IBC_TRANSFER_REPLY_ID))
+        .add_attribute("action", "withdraw_ibc")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("to", remote_address)
+        .add_attribute("amount", amount))
+}
+
+// IbcMsg::Transfer carries no memo, so the ICS-20 transfer is sent as a raw MsgTransfer.  The memo registers
+// this contract for the ibc-hooks lifecycle callback, which is what refunds rejected and timed out withdrawals.
+pub fn ibc_transfer_msg(env: &Env, channel_id: &str, remote_address: &str, amount: Uint128, timeout: Timestamp) -> CosmosMsg {
+    let memo = format!(r#"{{"ibc_callback":"{}"}}"#, env.contract.address);
+    let mut token = Vec::new();
+    proto_string(&mut token, 1, "usei");
+    proto_string(&mut token, 2, &amount.to_string());
+
+    let mut value = Vec::new();
+    proto_string(&mut value, 1, "transfer");
+    proto_string(&mut value, 2, channel_id);
+    proto_bytes(&mut value, 3, &token);
+    proto_string(&mut value, 4, env.contract.address.as_str());
+    proto_string(&mut value, 5, remote_address);
+    proto_varint(&mut value, 7 << 3);
+    proto_varint(&mut value, timeout.nanos());
+    proto_string(&mut value, 8, &memo);
+
+    CosmosMsg::Stargate {
+        type_url: "/ibc.applications.transfer.v1.MsgTransfer".to_string(),
+        value: Binary::from(value),
+    }
+}
+
+fn proto_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+// Length-delimited field (wire type 2).
+fn proto_bytes(buf: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    proto_varint(buf, (field << 3) | 2);
+    proto_varint(buf, bytes.len() as u64);
+    buf.extend_from_slice(bytes);
+}
+
+fn proto_string(buf: &mut Vec<u8>, field: u64, value: &str) {
+    proto_bytes(buf, field, value.as_bytes());
+}
+
+fn reply_ibc_transfer(deps: DepsMut, env: Env, result: SubMsgResult) -> Result<Response, ContractError> {
+    let transfer = PENDING_IBC_TRANSFER.load(deps.storage)?;
+    PENDING_IBC_TRANSFER.remove(deps.storage);
+
+    match result {
+        SubMsgResult::Ok(response) => {
+            // Remember the packet so that a failed acknowledgement or a timeout can be refunded.
+            let packet = response.events.iter().find(|e| e.ty == "send_packet").and_then(|e| {
+                let attr = |key: &str| e.attributes.iter().find(|a| a.key == key).map(|a| a.value.clone());
+                Some((attr("packet_src_channel")?, attr("packet_sequence")?.parse::<u64>().ok()?))
+            });
+            let (channel, sequence) = packet.ok_or_else(|| StdError::generic_err("send_packet event not found"))?;
+            IBC_TRANSFERS.save(deps.storage, (channel.clone(), sequence), &transfer)?;
+
+            Ok(Response::new()
+                .add_attribute("action", "ibc_transfer_sent")
+                .add_attribute("channel_id", channel)
+                .add_attribute("sequence", sequence.to_string()))
+        }
+        SubMsgResult::Err(err) => {
+            credit_balance(deps.storage, &env, transfer.sender.clone(), transfer.amount, EntryKind::WithdrawReverted, None)?;
+
+            Ok(Response::new()
+                .add_attribute("action", "ibc_transfer_refund")
+                .add_attribute("to", transfer.sender)
+                .add_attribute("amount", transfer.amount)
+                .add_attribute("error", err))
+        }
+    }
+}
+
+fn sudo_ibc_transfer_complete(deps: DepsMut, env: &Env, channel: String, sequence: u64, success: bool) -> Result<Response, ContractError> {
+    let key = (channel, sequence);
+    let transfer = match IBC_TRANSFERS.may_load(deps.storage, key.clone())? {
+        Some(transfer) => transfer,
+        // Not a withdrawal sent by this contract, nothing to do.
+        None => return Ok(Response::new()),
+    };
+    IBC_TRANSFERS.remove(deps.storage, key);
+
+    if success {
+        return Ok(Response::new().add_attribute("action", "ibc_transfer_complete"));
+    }
+    credit_balance(deps.storage, env, transfer.sender.clone(), transfer.amount, EntryKind::WithdrawReverted, None)?;
+    Ok(Response::new()
+        .add_attribute("action", "ibc_transfer_refund")
+        .add_attribute("to", transfer.sender)
+        .add_attribute("amount", transfer.amount))
+}
+
+pub fn execute_update_ibc_channels(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add: Vec<String>,
+    remove: Vec<String>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    for channel_id in add {
+        IBC_CHANNELS.save(deps.storage, channel_id, &true)?;
+    }
+    for channel_id in remove {
+        IBC_CHANNELS.remove(deps.storage, channel_id);
+    }
+
+    Ok(Response::new().add_attribute("action", "update_ibc_channels"))
+}
+
+// ibc-hooks turns an incoming ICS-20 transfer with a wasm memo into a call from an intermediate account, with the
+// received coins as info.funds.  Only usei is split, and it only arrives as "usei" when it is sei's own usei coming back,
+// whose denom trace is unwound.  Coins native to other chains arrive as "ibc/<hash>" vouchers and are refused.  The
+// transfer is recorded as sent by the intermediate account.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_ibc_hook_transfer(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel: String,
+    original_sender: String,
+    address1: Addr,
+    address2: Addr,
+    note: TransferNote,
+) -> Result<Response, ContractError> {
+    validate_note(&note)?;
+    if info.sender != ibc_hooks_sender(&channel, &original_sender)? {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let amount = info.funds.iter().filter(|c| c.denom == "usei").map(|c| c.amount).sum::<Uint128>();
+    if amount.is_zero() {
+        return Err(ContractError::InsufficientFundsSend {});
+    }
+
+    let half = amount.checked_div(2u128.into()).map_err(StdError::from)?;
+    let credits = vec![
+        credit_recipient(deps.storage, &env, &info.sender, address1, half)?,
+        credit_recipient(deps.storage, &env, &info.sender, address2, half)?,
+    ];
+    let receipt = record_transfer(deps.storage, &env, info.sender, credits, amount, "usei", note.clone())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "ibc_hook_transfer")
+        .add_attribute("channel", channel)
+        .add_attribute("original_sender", original_sender)
+        .add_attribute("amount", amount)
+        .add_attributes(transfer_attributes(receipt.transfer_id, note))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+pub fn execute_update_ledger_counterparties(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    add: Vec<LedgerCounterparty>,
+    remove: Vec<LedgerCounterparty>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    for counterparty in add {
+        LEDGER_COUNTERPARTIES.save(deps.storage, (counterparty.connection_id, counterparty.port_id), &true)?;
+    }
+    // Removing a counterparty leaves its channels connected, but they can no longer send or receive packets.
+    for counterparty in remove {
+        LEDGER_COUNTERPARTIES.remove(deps.storage, (counterparty.connection_id, counterparty.port_id));
+    }
+
+    Ok(Response::new().add_attribute("action", "update_ledger_counterparties"))
+}
+
+pub fn check_ledger_counterparty(storage: &dyn Storage, counterparty: &LedgerCounterparty) -> Result<(), ContractError> {
+    if !LEDGER_COUNTERPARTIES.has(storage, (counterparty.connection_id.clone(), counterparty.port_id.clone())) {
+        return Err(ContractError::UntrustedLedgerCounterparty {
+            connection_id: counterparty.connection_id.clone(),
+            port_id: counterparty.port_id.clone(),
+        });
+    }
+    Ok(())
+}
+
+// A ledger channel is usable while it is connected and its counterparty is still trusted.
+pub fn check_ledger_channel(storage: &dyn Storage, channel_id: &str) -> Result<(), ContractError> {
+    let counterparty = LEDGER_CHANNELS.may_load(storage, channel_id.to_string())?
+        .ok_or_else(|| ContractError::UnknownLedgerChannel { channel_id: channel_id.to_string() })?;
+    check_ledger_counterparty(storage, &counterparty)
+}
+
+// Ledger credits move usei between sister deployments: the sender is debited here and the accounts are credited there.
+// The usei never leaves this contract, so it is escrowed per channel until the vouchers issued against it come back.
+// A credit packet that fails or times out is refunded in the ibc module.
+pub fn execute_send_ledger_credits(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    credits: Vec<LedgerCredit>,
+    timeout: u64,
+) -> Result<Response, ContractError> {
+    check_ledger_channel(deps.storage, &channel_id)?;
+    let total = credits.iter().try_fold(Uint128::zero(), |acc, c| acc.checked_add(c.amount))
+        .map_err(StdError::from)?;
+    debit_balance(deps.storage, &env, info.sender.clone(), total, EntryKind::LedgerSend, None)?;
+    LEDGER_ESCROW.update(deps.storage, channel_id.clone(), |escrow| -> StdResult<_> {
+        Ok(escrow.unwrap_or_default().checked_add(total)?)
+    })?;
+
+    let packet = LedgerPacket::Credit { sender: info.sender.to_string(), credits };
+    let msg = IbcMsg::SendPacket {
+        channel_id: channel_id.clone(),
+        data: to_json_binary(&packet)?,
+        timeout: IbcTimeout::with_timestamp(env.block.time.plus_seconds(timeout)),
+    };
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "send_ledger_credits")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("amount", total))
+}
+
+pub fn execute_return_ledger_credits(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    channel_id: String,
+    credits: Vec<LedgerCredit>,
+    timeout: u64,
+) -> Result<Response, ContractError> {
+    check_ledger_channel(deps.storage, &channel_id)?;
+    let total = credits.iter().try_fold(Uint128::zero(), |acc, c| acc.checked_add(c.amount))
+        .map_err(StdError::from)?;
+    let key = (channel_id.clone(), info.sender.clone());
+    let vouchers = LEDGER_VOUCHERS.may_load(deps.storage, key.clone())?.unwrap_or_default();
+    let remaining = vouchers.checked_sub(total).map_err(|_| ContractError::InsufficientBalance {})?;
+    LEDGER_VOUCHERS.save(deps.storage, key, &remaining)?;
+
+    let packet = LedgerPacket::Return { sender: info.sender.to_string(), credits };
+    let msg = IbcMsg::SendPacket {
+        channel_id: channel_id.clone(),
+        data: to_json_binary(&packet)?,
+        timeout: IbcTimeout::with_timestamp(env.block.time.plus_seconds(timeout)),
+    };
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "return_ledger_credits")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("amount", total))
+}
+
+pub fn execute_query_remote_balance(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    channel_id: String,
+    address: String,
+    timeout: u64,
+) -> Result<Response, ContractError> {
+    check_ledger_channel(deps.storage, &channel_id)?;
+
+    let msg = IbcMsg::SendPacket {
+        channel_id: channel_id.clone(),
+        data: to_json_binary(&LedgerPacket::Balance { address: address.clone() })?,
+        timeout: IbcTimeout::with_timestamp(env.block.time.plus_seconds(timeout)),
+    };
+    Ok(Response::new()
+        .add_message(msg)
+        .add_attribute("action", "query_remote_balance")
+        .add_attribute("channel_id", channel_id)
+        .add_attribute("address", address))
+}
+
+// Swaps are dispatched as a submessage so that the proceeds can be checked against `min_out` in `reply`.
+pub fn execute_withdraw_and_swap(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    amount: Uint128,
+    denom: String,
+    ask_denom: String,
+    min_out: Uint128,
+) -> Result<Response, ContractError> {
+    if denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom });
+    }
+    let pair = SWAP_PAIRS.may_load(deps.storage, (denom.clone(), ask_denom.clone()))?
+        .ok_or_else(|| ContractError::SwapPairNotFound { offer_denom: denom.clone(), ask_denom: ask_denom.clone() })?;
+    debit_liquid_balance(deps.storage, &env, info.sender.clone(), amount)
+        .map_err(|e| match e {
+            ContractError::InsufficientBalance {} => ContractError::InsufficientBalanceForWithdraw {},
+            e => e,
+        })?;
+
+    let balance_before = deps.querier.query_balance(&env.contract.address, &ask_denom)?.amount;
+    PENDING_SWAP.save(deps.storage, &PendingSwap { recipient: info.sender.clone(), ask_denom: ask_denom.clone(), min_out, balance_before })?;
+
+    let swap = WasmMsg::Execute {
+        contract_addr: pair.to_string(),
+        msg: to_json_binary(&PairExecuteMsg::Swap {
+            offer_asset: Asset { info: AssetInfo::NativeToken { denom: denom.clone() }, amount },
+            belief_price: None,
+            max_spread: None,
+            to: None,
+        })?,
+        funds: vec![Coin { denom, amount }],
+    };
+    Ok(Response::new()
+        .add_submessage(SubMsg::reply_on_success(swap, SWAP_REPLY_ID))
+        .add_attribute("action", "withdraw_and_swap")
+        .add_attribute("amount", amount)
+        .add_attribute("ask_denom", ask_denom))
+}
+
+fn reply_swap(deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let swap = PENDING_SWAP.load(deps.storage)?;
+    PENDING_SWAP.remove(deps.storage);
+
+    let balance_after = deps.querier.query_balance(&env.contract.address, &swap.ask_denom)?.amount;
+    let received = balance_after.checked_sub(swap.balance_before).map_err(StdError::from)?;
+    // Failing here reverts the whole withdrawal, including the debit and the swap.
+    if received < swap.min_out {
+        return Err(ContractError::SwapBelowMinimum { received, min_out: swap.min_out });
+    }
+
+    Ok(send_tokens(swap.recipient, vec![Coin { denom: swap.ask_denom, amount: received }], "swap_proceeds")
+        .add_attribute("amount", received))
+}
+
+pub fn execute_update_swap_pair(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    offer_denom: String,
+    ask_denom: String,
+    pair: Option<Addr>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    match pair {
+        Some(pair) => {
+            let pair = deps.api.addr_validate(pair.as_str())?;
+            SWAP_PAIRS.save(deps.storage, (offer_denom, ask_denom), &pair)?;
+        }
+        None => SWAP_PAIRS.remove(deps.storage, (offer_denom, ask_denom)),
+    }
+
+    Ok(Response::new().add_attribute("action", "update_swap_pair"))
+}
+
+// Converts micro-USD to usei at the current oracle price, rounding up so the payee is never short.
+fn usd_to_usei(
+    deps: Deps,
+    env: &Env,
+    usd_amount: Uint128,
+    belief_price: Decimal,
+    max_slippage: Decimal,
+) -> Result<Uint128, ContractError> {
+    let config = ORACLE.may_load(deps.storage)?.ok_or(ContractError::OracleNotConfigured {})?;
+    let resp: OraclePriceResponse = deps.querier.query_wasm_smart(&config.oracle, &OracleQueryMsg::Price { denom: "usei".to_string() })?;
+
+    if env.block.time.seconds().saturating_sub(resp.updated_at.seconds()) > config.max_price_age {
+        return Err(ContractError::PriceTooOld { max_price_age: config.max_price_age });
+    }
+    let deviation = if resp.price > belief_price { resp.price - belief_price } else { belief_price - resp.price };
+    if resp.price.is_zero() || deviation > belief_price * max_slippage {
+        return Err(ContractError::MaxSlippageExceeded { price: resp.price });
+    }
+
+    Ok(usd_amount.checked_div_ceil(resp.price).map_err(|e| StdError::generic_err(e.to_string()))?)
+}
+
+// Like Transfer, for `usd_amount` micro-USD converted to usei at the oracle price.  Any usei sent beyond that is refunded.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_transfer_usd(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address1: Addr,
+    address2: Addr,
+    usd_amount: Uint128,
+    belief_price: Decimal,
+    max_slippage: Decimal,
+    note: TransferNote,
+) -> Result<Response, ContractError> {
+    let amount = usd_to_usei(deps.as_ref(), &env, usd_amount, belief_price, max_slippage)?;
+    let res = execute_transfer(deps.branch(), env, info.clone(), address1, address2, amount, note)?;
+    Ok(refund_excess(deps.storage, res, &info, amount)?)
+}
+
+// Like TransferWithTip, for `usd_amount` micro-USD.  Any usei sent beyond the converted amount and the tip is refunded.
+#[allow(clippy::too_many_arguments)]
+pub fn execute_transfer_usd_with_tip(
+    mut deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    address1: Addr,
+    address2: Addr,
+    usd_amount: Uint128,
+    belief_price: Decimal,
+    max_slippage: Decimal,
+    note: TransferNote,
+) -> Result<Response, ContractError> {
+    let amount = usd_to_usei(deps.as_ref(), &env, usd_amount, belief_price, max_slippage)?;
+    let used = amount.checked_add(Uint128::from(TRANSFER_TIP)).map_err(StdError::from)?;
+    let res = execute_transfer_with_tip(deps.branch(), env, info.clone(), address1, address2, amount, note)?;
+    Ok(refund_excess(deps.storage, res, &info, used)?)
+}
+
+// Returns any usei sent beyond `used` to the sender, and notes the refund on the transfer's receipt.
+fn refund_excess(storage: &mut dyn Storage, res: Response, info: &MessageInfo, used: Uint128) -> StdResult<Response> {
+    let sent = info.funds.iter().filter(|c| c.denom == "usei").map(|c| c.amount).sum::<Uint128>();
+    let excess = sent.saturating_sub(used);
+    if excess.is_zero() {
+        return Ok(res);
+    }
+    let mut receipt: TransferReceipt = from_json(res.data.as_ref().ok_or_else(|| StdError::generic_err("Transfer returned no receipt"))?)?;
+    receipt.refund = excess;
+    let data = save_receipt(storage, &receipt)?;
+    Ok(res.add_message(BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: vec![Coin { denom: "usei".to_string(), amount: excess }],
+    })
+    .add_attribute("refund", excess)
+    .set_data(data))
+}
+
+pub fn execute_update_oracle(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    oracle: Addr,
+    max_price_age: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let oracle = deps.api.addr_validate(oracle.as_str())?;
+    ORACLE.save(deps.storage, &OracleConfig { oracle, max_price_age })?;
+
+    Ok(Response::new().add_attribute("action", "update_oracle"))
+}
+
+pub fn execute_update_idempotency_window(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    blocks: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    IDEMPOTENCY_WINDOW.save(deps.storage, &blocks)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_idempotency_window")
+        .add_attribute("blocks", blocks.to_string()))
+}
+
+pub fn execute_update_staking(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    validators: Vec<String>,
+    buffer_ratio: Decimal,
+    unbonding_period: u64,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if buffer_ratio > Decimal::one() {
+        return Err(ContractError::InvalidBufferRatio {});
+    }
+
+    STAKING.save(deps.storage, &StakingConfig { validators, buffer_ratio, unbonding_period })?;
+    if REWARD_INDEX.may_load(deps.storage)?.is_none() {
+        REWARD_INDEX.save(deps.storage, &Decimal::zero(), env.block.height)?;
+    }
+
+    Ok(Response::new().add_attribute("action", "update_staking"))
+}
+
+// Rewards are claimed from every validator the contract delegates to.  The last claim replies, so the rewards can be
+// measured and distributed before the delegations are adjusted.
+pub fn execute_rebalance(
+    mut deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let staking = STAKING.may_load(deps.storage)?.ok_or(ContractError::StakingNotConfigured {})?;
+    let validators = DELEGATIONS.keys(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let claims = claim_rewards(deps.branch(), &env, validators, REWARDS_REPLY_ID)?;
+    if claims.is_empty() {
+        return Ok(Response::new()
+            .add_messages(rebalance_delegations(deps.storage, &staking)?)
+            .add_attribute("action", "rebalance"));
+    }
+
+    Ok(Response::new()
+        .add_submessages(claims)
+        .add_attribute("action", "rebalance"))
+}
+
+// Claims the rewards pending on `validators`.  The last claim replies with `reply_id`, where
+// `distribute_claimed_rewards` measures what arrived.  Returns no messages when there is nothing to claim.
+fn claim_rewards(deps: DepsMut, env: &Env, validators: Vec<String>, reply_id: u64) -> StdResult<Vec<SubMsg>> {
+    let mut pending = vec![];
+    for validator in validators {
+        let delegation = deps.querier.query_delegation(&env.contract.address, &validator)?;
+        if delegation.is_some_and(|d| d.accumulated_rewards.iter().any(|c| c.denom == "usei" && !c.amount.is_zero())) {
+            pending.push(validator);
+        }
+    }
+    let mut validators = pending;
+    let last = match validators.pop() {
+        Some(last) => last,
+        None => return Ok(vec![]),
+    };
+
+    let balance = deps.querier.query_balance(&env.contract.address, "usei")?.amount;
+    PENDING_REWARD_CLAIM.save(deps.storage, &balance)?;
+    let mut msgs = validators.into_iter()
+        .map(|validator| SubMsg::new(DistributionMsg::WithdrawDelegatorReward { validator }))
+        .collect::<Vec<_>>();
+    msgs.push(SubMsg::reply_on_success(DistributionMsg::WithdrawDelegatorReward { validator: last }, reply_id));
+    Ok(msgs)
+}
+
+fn distribute_claimed_rewards(deps: DepsMut, env: &Env) -> StdResult<Uint128> {
+    let balance_before = PENDING_REWARD_CLAIM.load(deps.storage)?;
+    PENDING_REWARD_CLAIM.remove(deps.storage);
+
+    let balance_after = deps.querier.query_balance(&env.contract.address, "usei")?.amount;
+    let rewards = balance_after.saturating_sub(balance_before);
+    distribute_rewards(deps.storage, env, rewards)?;
+    Ok(rewards)
+}
+
+fn reply_rewards(mut deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let rewards = distribute_claimed_rewards(deps.branch(), &env)?;
+
+    let staking = STAKING.load(deps.storage)?;
+    Ok(Response::new()
+        .add_messages(rebalance_delegations(deps.storage, &staking)?)
+        .add_attribute("action", "distribute_rewards")
+        .add_attribute("rewards", rewards))
+}
+
+// Shares `rewards` pro rata among all account holders by raising the global reward index.  Each account picks up its
+// share the next time its balance is read or changed.
+fn distribute_rewards(storage: &mut dyn Storage, env: &Env, rewards: Uint128) -> StdResult<()> {
+    if rewards.is_zero() {
+        return Ok(());
+    }
+    let total = TOTAL_BALANCE.may_load(storage)?.unwrap_or_default();
+    if total.is_zero() {
+        // Nobody to share with.
+        let config = CONFIG.load(storage)?;
+        credit_balance(storage, env, config.owner, rewards, EntryKind::Reward, None)?;
+        return Ok(());
+    }
+
+    let index = REWARD_INDEX.may_load(storage)?.unwrap_or_default();
+    REWARD_INDEX.save(storage, &(index + Decimal::from_ratio(rewards, total)), env.block.height)?;
+    TOTAL_BALANCE.save(storage, &total.checked_add(rewards)?, env.block.height)?;
+    Ok(())
+}
+
+// Splits `amount` into `parts` equal shares, with the remainder going to the first.
+fn split_evenly(amount: Uint128, parts: usize) -> Vec<Uint128> {
+    let share = amount / Uint128::from(parts as u128);
+    let mut shares = vec![share; parts];
+    shares[0] += amount - share * Uint128::from(parts as u128);
+    shares
+}
+
+// Moves delegations off validators that are no longer configured and delegates everything above the buffer.
+// Delegations are never reduced here; withdrawals unbond what they need.
+fn rebalance_delegations(storage: &mut dyn Storage, staking: &StakingConfig) -> StdResult<Vec<StakingMsg>> {
+    let mut msgs = vec![];
+    if staking.validators.is_empty() {
+        return Ok(msgs);
+    }
+
+    let removed = DELEGATIONS.range(storage, None, None, Order::Ascending)
+        .filter(|item| item.as_ref().map_or(true, |(validator, _)| !staking.validators.contains(validator)))
+        .collect::<StdResult<Vec<_>>>()?;
+    for (src_validator, amount) in removed {
+        DELEGATIONS.remove(storage, src_validator.clone());
+        for (dst_validator, share) in staking.validators.iter().zip(split_evenly(amount, staking.validators.len())) {
+            if share.is_zero() {
+                continue;
+            }
+            add_delegation(storage, dst_validator, share)?;
+            msgs.push(StakingMsg::Redelegate {
+                src_validator: src_validator.clone(),
+                dst_validator: dst_validator.clone(),
+                amount: Coin { denom: "usei".to_string(), amount: share },
+            });
+        }
+    }
+
+    let total = TOTAL_BALANCE.may_load(storage)?.unwrap_or_default();
+    let target = total * (Decimal::one() - staking.buffer_ratio);
+    let surplus = target.saturating_sub(total_staked(storage)?);
+    if surplus.is_zero() {
+        return Ok(msgs);
+    }
+    for (validator, share) in staking.validators.iter().zip(split_evenly(surplus, staking.validators.len())) {
+        if share.is_zero() {
+            continue;
+        }
+        add_delegation(storage, validator, share)?;
+        msgs.push(StakingMsg::Delegate {
+            validator: validator.clone(),
+            amount: Coin { denom: "usei".to_string(), amount: share },
+        });
+    }
+    Ok(msgs)
+}
+
+fn add_delegation(storage: &mut dyn Storage, validator: &str, amount: Uint128) -> StdResult<Uint128> {
+    DELEGATIONS.update(storage, validator.to_string(), |d| -> StdResult<_> { Ok(d.unwrap_or_default().checked_add(amount)?) })
+}
+
+// The rewards are shared before the withdrawal is debited, so the withdrawing account gets its part too.
+fn reply_withdraw_rewards(mut deps: DepsMut, env: Env) -> Result<Response, ContractError> {
+    let rewards = distribute_claimed_rewards(deps.branch(), &env)?;
+
+    let (address, amount) = PENDING_WITHDRAWAL.load(deps.storage)?;
+    PENDING_WITHDRAWAL.remove(deps.storage);
+    Ok(withdraw_from_ledger(deps.storage, &env, address, amount)?
+        .add_attribute("rewards", rewards))
+}
+
+// The validators `undelegate` takes `amount` usei from.
+fn validators_to_unbond(storage: &dyn Storage, amount: Uint128) -> StdResult<Vec<String>> {
+    let mut validators = vec![];
+    let mut remaining = amount;
+    for item in DELEGATIONS.range(storage, None, None, Order::Ascending) {
+        if remaining.is_zero() {
+            break;
+        }
+        let (validator, delegated) = item?;
+        remaining = remaining.saturating_sub(delegated);
+        validators.push(validator);
+    }
+    Ok(validators)
+}
+
+// Undelegates `amount` usei, taking from the validators in order.
+fn undelegate(storage: &mut dyn Storage, amount: Uint128) -> StdResult<Vec<StakingMsg>> {
+    let delegations = DELEGATIONS.range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut msgs = vec![];
+    let mut remaining = amount;
+    for (validator, delegated) in delegations {
+        if remaining.is_zero() {
+            break;
+        }
+        let unbond = delegated.min(remaining);
+        remaining -= unbond;
+        if unbond == delegated {
+            DELEGATIONS.remove(storage, validator.clone());
+        } else {
+            DELEGATIONS.save(storage, validator.clone(), &(delegated - unbond))?;
+        }
+        msgs.push(StakingMsg::Undelegate { validator, amount: Coin { denom: "usei".to_string(), amount: unbond } });
+    }
+    if !remaining.is_zero() {
+        return Err(StdError::generic_err("not enough usei delegated"));
+    }
+    Ok(msgs)
+}
+
+pub fn execute_claim_withdrawal(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let withdrawals = UNBONDING_WITHDRAWALS.may_load(deps.storage, info.sender.clone())?.unwrap_or_default();
+    let (released, pending): (Vec<_>, Vec<_>) = withdrawals.into_iter().partition(|w| w.release_at <= env.block.time);
+    if released.is_empty() {
+        return Err(ContractError::NothingToClaim {});
+    }
+
+    if pending.is_empty() {
+        UNBONDING_WITHDRAWALS.remove(deps.storage, info.sender.clone());
+    } else {
+        UNBONDING_WITHDRAWALS.save(deps.storage, info.sender.clone(), &pending)?;
+    }
+
+    let amount = released.iter().map(|w| w.amount).sum::<Uint128>();
+    Ok(send_tokens(info.sender.clone(), vec![Coin { denom: "usei".to_string(), amount }], "claim_withdrawal")
+        .add_attribute("amount", amount))
+}
+
+// Raises the fee-per-share accumulator by `fee`, so distribution costs the same however many shareholders there are.
+// Returns the owner if the fee was credited to them instead.
+fn collect_fee(storage: &mut dyn Storage, env: &Env, fee: Uint128) -> StdResult<Option<Addr>> {
+    let total_shares = TOTAL_SHARES.may_load(storage)?.unwrap_or_default();
+    if total_shares.is_zero() {
+        // Credit contract owner with the tip.
+        let config = CONFIG.load(storage)?;
+        credit_balance(storage, env, config.owner.clone(), fee, EntryKind::TipReceived, None)?;
+        return Ok(Some(config.owner));
+    }
+
+    let fee_per_share = FEE_PER_SHARE.may_load(storage)?.unwrap_or_default();
+    FEE_PER_SHARE.save(storage, &(fee_per_share + Decimal::from_ratio(fee, total_shares)))?;
+    Ok(None)
+}
+
+// Moves the fees earned since the last settlement into `unclaimed`.
+fn settle_fees(storage: &dyn Storage, mut shareholder: Shareholder) -> StdResult<Shareholder> {
+    let fee_per_share = FEE_PER_SHARE.may_load(storage)?.unwrap_or_default();
+    let earned = shareholder.shares * (fee_per_share - shareholder.fee_per_share_snapshot);
+    shareholder.unclaimed = shareholder.unclaimed.checked_add(earned)?;
+    shareholder.fee_per_share_snapshot = fee_per_share;
+    Ok(shareholder)
+}
+
+pub fn execute_update_shareholders(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    shareholders: Vec<ShareholderMsg>,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    let mut total_shares = TOTAL_SHARES.may_load(deps.storage)?.unwrap_or_default();
+    for ShareholderMsg { address, shares } in shareholders {
+        let address = deps.api.addr_validate(address.as_str())?;
+        let shareholder = match SHAREHOLDERS.may_load(deps.storage, address.clone())? {
+            // Pending fees are settled at the old share count before it changes.
+            Some(shareholder) => settle_fees(deps.storage, shareholder)?,
+            None => Shareholder {
+                shares: Uint128::zero(),
+                fee_per_share_snapshot: FEE_PER_SHARE.may_load(deps.storage)?.unwrap_or_default(),
+                unclaimed: Uint128::zero(),
+            },
+        };
+        total_shares = total_shares.checked_sub(shareholder.shares).map_err(StdError::from)?
+            .checked_add(shares).map_err(StdError::from)?;
+
+        if shares.is_zero() && shareholder.unclaimed.is_zero() {
+            SHAREHOLDERS.remove(deps.storage, address);
+        } else {
+            SHAREHOLDERS.save(deps.storage, address, &Shareholder { shares, ..shareholder })?;
+        }
+    }
+    TOTAL_SHARES.save(deps.storage, &total_shares)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_shareholders")
+        .add_attribute("total_shares", total_shares))
+}
+
+pub fn execute_claim_fees(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+) -> Result<Response, ContractError> {
+    let shareholder = SHAREHOLDERS.may_load(deps.storage, info.sender.clone())?
+        .ok_or(ContractError::NoFeesToClaim {})?;
+    let shareholder = settle_fees(deps.storage, shareholder)?;
+    let amount = shareholder.unclaimed;
+    if amount.is_zero() {
+        return Err(ContractError::NoFeesToClaim {});
+    }
+
+    if shareholder.shares.is_zero() {
+        SHAREHOLDERS.remove(deps.storage, info.sender.clone());
+    } else {
+        SHAREHOLDERS.save(deps.storage, info.sender.clone(), &Shareholder { unclaimed: Uint128::zero(), ..shareholder })?;
+    }
+    record_entry(deps.storage, &env, info.sender.clone(), EntryKind::FeeClaim, None, amount, "usei")?;
+
+    Ok(send_tokens(info.sender.clone(), vec![Coin { denom: "usei".to_string(), amount }], "claim_fees")
+        .add_attribute("amount", amount))
+}
+
+// Lists every member of `group` with a non-zero weight, walking all pages of ListMembers.
+fn list_group_members(querier: &QuerierWrapper, group: &Cw4Contract) -> StdResult<Vec<(String, u64)>> {
+    let mut members = vec![];
+    let mut start_after = None;
+    loop {
+        let page = group.list_members(querier, start_after, Some(MAX_LIMIT))?;
+        if page.is_empty() {
+            return Ok(members);
+        }
+        start_after = page.last().map(|m| m.addr.clone());
+        members.extend(page.into_iter().filter(|m| m.weight > 0).map(|m| (m.addr, m.weight)));
+    }
+}
+
+// Members with weight, either listed by the group now or, with `at_height`, as recorded from its hook.
+fn group_members(deps: Deps, env: &Env, group: &Cw4Contract, at_height: Option<u64>) -> Result<Vec<(String, u64)>, ContractError> {
+    let height = match at_height {
+        Some(height) => height,
+        None => return Ok(list_group_members(&deps.querier, group)?),
+    };
+
+    let not_tracked = || ContractError::GroupNotTracked { group: group.addr().to_string() };
+    let since = TRACKED_GROUPS.may_load(deps.storage, group.addr())?.ok_or_else(not_tracked)?;
+    // Recorded weights become readable from the block after they were saved.
+    if height <= since {
+        return Err(ContractError::GroupHistoryUnavailable { since });
+    }
+    // Without the hook, or with recorded members that differ from the group's, changes were missed and the history
+    // can't be trusted.
+    if !group.hooks(&deps.querier)?.contains(&env.contract.address.to_string()) || !group_history_intact(deps, group)? {
+        return Err(not_tracked());
+    }
+
+    let mut members = vec![];
+    for member in GROUP_WEIGHTS.prefix(group.addr()).keys(deps.storage, None, None, Order::Ascending) {
+        let member = member?;
+        let weight = GROUP_WEIGHTS.may_load_at_height(deps.storage, (group.addr(), member.clone()), height)?.unwrap_or_default();
+        if weight > 0 {
+            members.push((member.into_string(), weight));
+        }
+    }
+    Ok(members)
+}
+
+// Whether the latest weights recorded for `group` are exactly its current members and weights.
+fn group_history_intact(deps: Deps, group: &Cw4Contract) -> StdResult<bool> {
+    let recorded = GROUP_WEIGHTS.prefix(group.addr()).range(deps.storage, None, None, Order::Ascending)
+        .filter(|item| item.as_ref().map_or(true, |(_, weight)| *weight > 0))
+        .map(|item| item.map(|(member, weight)| (member.into_string(), weight)))
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(list_group_members(&deps.querier, group)? == recorded)
+}
+
+// Recording starts from the group's current members.  The group must already call this contract on changes.  Tracking
+// a group again whose recorded members no longer match, because it dropped the hook for a while, starts over.
+pub fn execute_track_group(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    group_contract: Addr,
+) -> Result<Response, ContractError> {
+    let group = Cw4Contract::new(deps.api.addr_validate(group_contract.as_str())?);
+    if !group.hooks(&deps.querier)?.contains(&env.contract.address.to_string()) {
+        return Err(ContractError::GroupNotTracked { group: group.addr().to_string() });
+    }
+    if TRACKED_GROUPS.has(deps.storage, group.addr()) {
+        if group_history_intact(deps.as_ref(), &group)? {
+            return Ok(Response::new().add_attribute("action", "track_group"));
+        }
+        let recorded = GROUP_WEIGHTS.prefix(group.addr()).keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for member in recorded {
+            GROUP_WEIGHTS.save(deps.storage, (group.addr(), member), &0, env.block.height)?;
+        }
+    }
+
+    for (member, weight) in list_group_members(&deps.querier, &group)? {
+        GROUP_WEIGHTS.save(deps.storage, (group.addr(), deps.api.addr_validate(&member)?), &weight, env.block.height)?;
+    }
+    TRACKED_GROUPS.save(deps.storage, group.addr(), &env.block.height)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "track_group")
+        .add_attribute("group", group.addr())
+        .add_attribute("since", env.block.height.to_string()))
+}
+
+// Hooks from groups that are not tracked are ignored, since failing here would block the group's own update.
+pub fn execute_member_changed_hook(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    hook: MemberChangedHookMsg,
+) -> Result<Response, ContractError> {
+    let group = info.sender;
+    if !TRACKED_GROUPS.has(deps.storage, group.clone()) {
+        return Ok(Response::new());
+    }
+
+    for diff in hook.diffs {
+        let member = deps.api.addr_validate(&diff.key)?;
+        GROUP_WEIGHTS.save(deps.storage, (group.clone(), member), &diff.new.unwrap_or_default(), env.block.height)?;
+    }
+
+    Ok(Response::new()
+        .add_attribute("action", "member_changed_hook")
+        .add_attribute("group", group))
+}
+
+// Each member is credited its weight's share of `amount`, rounded down.  The remainder goes to the heaviest member,
+// the first listed on ties.
+fn credit_by_weight(
+    storage: &mut dyn Storage,
+    env: &Env,
+    sender: &Addr,
+    amount: Uint128,
+    members: Vec<(Addr, u64)>,
+    note: TransferNote,
+) -> StdResult<TransferReceipt> {
+    let total_weight = members.iter().map(|(_, weight)| u128::from(*weight)).sum::<u128>();
+    let mut shares = members.iter()
+        .map(|(address, weight)| (address.clone(), amount.multiply_ratio(*weight, total_weight)))
+        .collect::<Vec<_>>();
+    let distributed = shares.iter().map(|(_, share)| *share).sum::<Uint128>();
+    let heaviest = members.iter().enumerate().max_by_key(|(i, (_, weight))| (*weight, std::cmp::Reverse(*i))).map(|(i, _)| i).unwrap();
+    shares[heaviest].1 += amount - distributed;
+
+    let credits = shares.into_iter()
+        .map(|(address, share)| credit_recipient(storage, env, sender, address, share))
+        .collect::<StdResult<Vec<_>>>()?;
+    record_transfer(storage, env, sender.clone(), credits, amount, "usei", note)
+}
+
+pub fn execute_transfer_to_group(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    group_contract: Addr,
+    at_height: Option<u64>,
+    note: TransferNote,
+) -> Result<Response, ContractError> {
+    validate_note(&note)?;
+    let funds = one_coin(&info)?;
+    if funds.denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom: funds.denom });
+    }
+
+    let group = Cw4Contract::new(deps.api.addr_validate(group_contract.as_str())?);
+    let members = group_members(deps.as_ref(), &env, &group, at_height)?;
+    if members.is_empty() {
+        return Err(ContractError::EmptyGroup {});
+    }
+    let count = members.len();
+    let members = members.into_iter()
+        .map(|(addr, weight)| Ok((deps.api.addr_validate(&addr)?, weight)))
+        .collect::<StdResult<Vec<_>>>()?;
+    let receipt = credit_by_weight(deps.storage, &env, &info.sender, funds.amount, members, note.clone())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_to_group")
+        .add_attribute("group", group.addr())
+        .add_attribute("members", count.to_string())
+        .add_attribute("amount", funds.amount)
+        .add_attributes(transfer_attributes(receipt.transfer_id, note))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+fn validate_split_members(deps: Deps, members: &[SplitMember]) -> Result<(), ContractError> {
+    let mut addresses = vec![];
+    for member in members {
+        let address = deps.api.addr_validate(member.address.as_str())?;
+        if addresses.contains(&address) {
+            return Err(ContractError::InvalidSplitGroup {});
+        }
+        addresses.push(address);
+    }
+    if members.iter().all(|m| m.weight == 0) {
+        return Err(ContractError::InvalidSplitGroup {});
+    }
+    Ok(())
+}
+
+pub fn execute_create_split_group(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    name: String,
+    members: Vec<SplitMember>,
+) -> Result<Response, ContractError> {
+    validate_split_members(deps.as_ref(), &members)?;
+
+    let id = SPLIT_GROUP_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    SPLIT_GROUP_COUNT.save(deps.storage, &id)?;
+    SPLIT_GROUP_VERSIONS.save(deps.storage, (id, 1), &members)?;
+    split_groups().save(deps.storage, id, &SplitGroup { name, admin: info.sender.clone(), members, version: 1 })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_split_group")
+        .add_attribute("split_group_id", id.to_string()))
+}
+
+pub fn execute_update_split_group(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: u64,
+    members: Vec<SplitMember>,
+) -> Result<Response, ContractError> {
+    let mut group = split_groups().load(deps.storage, id)?;
+    if info.sender != group.admin {
+        return Err(ContractError::Unauthorized {});
+    }
+    validate_split_members(deps.as_ref(), &members)?;
+
+    group.version += 1;
+    group.members = members;
+    SPLIT_GROUP_VERSIONS.save(deps.storage, (id, group.version), &group.members)?;
+    split_groups().save(deps.storage, id, &group)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_split_group")
+        .add_attribute("split_group_id", id.to_string())
+        .add_attribute("version", group.version.to_string()))
+}
+
+// The response records the version paid, so the weights used can be looked up later with SplitGroupVersion.
+pub fn execute_transfer_to_split_group(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    note: TransferNote,
+) -> Result<Response, ContractError> {
+    validate_note(&note)?;
+    let funds = one_coin(&info)?;
+    if funds.denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom: funds.denom });
+    }
+
+    let group = split_groups().load(deps.storage, id)?;
+    let members = group.members.into_iter()
+        .filter(|m| m.weight > 0)
+        .map(|m| (m.address, m.weight))
+        .collect();
+    let mut receipt = credit_by_weight(deps.storage, &env, &info.sender, funds.amount, members, note.clone())?;
+    receipt.split_group = Some(SplitGroupRef { id, version: group.version });
+
+    Ok(Response::new()
+        .add_attribute("action", "transfer_to_split_group")
+        .add_attribute("split_group_id", id.to_string())
+        .add_attribute("version", group.version.to_string())
+        .add_attribute("amount", funds.amount)
+        .add_attributes(transfer_attributes(receipt.transfer_id, note))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+pub fn execute_create_invoice(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    id: String,
+    invoice: Invoice,
+) -> Result<Response, ContractError> {
+    if id.is_empty() || id.len() > MAX_REFERENCE_LENGTH {
+        return Err(ContractError::InvalidInvoiceId { max: MAX_REFERENCE_LENGTH });
+    }
+    if invoice.denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom: invoice.denom });
+    }
+    if invoice.amount.is_zero() || invoice.expiry <= env.block.time {
+        return Err(ContractError::InvalidInvoice {});
+    }
+    if let Some(payer) = &invoice.payer {
+        deps.api.addr_validate(payer.as_str())?;
+    }
+    if !invoice.split.is_empty() {
+        validate_split_members(deps.as_ref(), &invoice.split)?;
+    }
+    let key = (invoice.payee.clone(), id.clone());
+    if invoices().has(deps.storage, key.clone()) {
+        return Err(ContractError::InvoiceExists { id });
+    }
+
+    invoices().save(deps.storage, key, &invoice)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_invoice")
+        .add_attribute("invoice_id", id)
+        .add_attribute("payee", invoice.payee)
+        .add_attribute("amount", invoice.amount))
+}
+
+// The payment is split like TransferToSplitGroup, with the invoice id as its reference.
+pub fn execute_pay_invoice(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    payee: Addr,
+    id: String,
+) -> Result<Response, ContractError> {
+    let key = (payee, id.clone());
+    let mut invoice = invoices().load(deps.storage, key.clone())?;
+    if invoice.status != InvoiceStatus::Open {
+        return Err(ContractError::InvoiceNotOpen {});
+    }
+    if env.block.time >= invoice.expiry {
+        return Err(ContractError::InvoiceExpired {});
+    }
+    if invoice.payer.as_ref().is_some_and(|payer| *payer != info.sender) {
+        return Err(ContractError::Unauthorized {});
+    }
+    let expected = Coin { denom: invoice.denom.clone(), amount: invoice.amount };
+    if one_coin(&info)? != expected {
+        return Err(ContractError::InvoicePaymentMismatch { expected });
+    }
+
+    let members = if invoice.split.is_empty() {
+        vec![(invoice.payee.clone(), 1)]
+    } else {
+        invoice.split.iter().filter(|m| m.weight > 0).map(|m| (m.address.clone(), m.weight)).collect()
+    };
+    let note = TransferNote { memo: None, reference: Some(id.clone()) };
+    let receipt = credit_by_weight(deps.storage, &env, &info.sender, invoice.amount, members, note.clone())?;
+
+    invoice.status = InvoiceStatus::Paid;
+    invoice.paid_by = Some(info.sender);
+    invoices().save(deps.storage, key, &invoice)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "pay_invoice")
+        .add_attribute("payee", invoice.payee)
+        .add_attribute("invoice_id", id)
+        .add_attributes(transfer_attributes(receipt.transfer_id, note))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+pub fn execute_cancel_invoice(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: String,
+) -> Result<Response, ContractError> {
+    // Invoices are looked up under the sender, so only the payee can reach its own.
+    let key = (info.sender, id.clone());
+    let mut invoice = invoices().load(deps.storage, key.clone())?;
+    if invoice.status != InvoiceStatus::Open {
+        return Err(ContractError::InvoiceNotOpen {});
+    }
+
+    invoice.status = InvoiceStatus::Cancelled;
+    invoices().save(deps.storage, key, &invoice)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "cancel_invoice")
+        .add_attribute("invoice_id", id))
+}
+
+pub fn execute_expire_invoice(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    payee: Addr,
+    id: String,
+) -> Result<Response, ContractError> {
+    let key = (payee, id.clone());
+    let mut invoice = invoices().load(deps.storage, key.clone())?;
+    if invoice.status != InvoiceStatus::Open {
+        return Err(ContractError::InvoiceNotOpen {});
+    }
+    if env.block.time < invoice.expiry {
+        return Err(ContractError::InvoiceNotExpired {});
+    }
+
+    invoice.status = InvoiceStatus::Expired;
+    invoices().save(deps.storage, key, &invoice)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "expire_invoice")
+        .add_attribute("payee", invoice.payee)
+        .add_attribute("invoice_id", id))
+}
+
+fn invoice_response(id: String, invoice: Invoice) -> InvoiceResponse {
+    InvoiceResponse {
+        id,
+        payee: invoice.payee,
+        payer: invoice.payer,
+        amount: invoice.amount,
+        denom: invoice.denom,
+        expiry: invoice.expiry,
+        split: invoice.split,
+        status: invoice.status,
+        paid_by: invoice.paid_by,
+    }
+}
+
+fn get_invoice_resolver(deps: Deps, _env: Env, payee: Addr, id: String) -> StdResult<Binary> {
+    let invoice = invoices().load(deps.storage, (payee, id.clone()))?;
+    to_json_binary(&invoice_response(id, invoice))
+}
+
+fn get_invoices_by_payee_resolver(deps: Deps, _env: Env, payee: Addr, start_after: Option<String>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let min = start_after.map(|id| Bound::exclusive((payee.clone(), id)));
+    let invoices = invoices().idx.payee.prefix(payee)
+        .range(deps.storage, min, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((_, id), invoice)| invoice_response(id, invoice)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&InvoicesResponse { invoices })
+}
+
+fn get_invoices_by_payer_resolver(
+    deps: Deps,
+    _env: Env,
+    payer: Addr,
+    start_after: Option<(Addr, String)>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let invoices = invoices().idx.payer.prefix(payer.to_string())
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((_, id), invoice)| invoice_response(id, invoice)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&InvoicesResponse { invoices })
+}
+
+fn get_invoices_by_status_resolver(
+    deps: Deps,
+    _env: Env,
+    status: InvoiceStatus,
+    start_after: Option<(Addr, String)>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let invoices = invoices().idx.status.prefix(status as u8)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((_, id), invoice)| invoice_response(id, invoice)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&InvoicesResponse { invoices })
+}
+
+// Reserves part of the sender's available balance for a merchant.  Nothing moves until the hold is captured.
+pub fn execute_authorize_hold(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    merchant: Addr,
+    amount: Uint128,
+    expiry: Timestamp,
+) -> Result<Response, ContractError> {
+    let merchant = deps.api.addr_validate(merchant.as_str())?;
+    if expiry <= env.block.time {
+        return Err(ContractError::HoldExpired {});
+    }
+    let available = balance_of(deps.storage, &info.sender)?.saturating_sub(held_balance(deps.storage, &env, &info.sender)?);
+    if available < amount {
+        return Err(ContractError::InsufficientBalance {});
+    }
+
+    let id = HOLD_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    HOLD_COUNT.save(deps.storage, &id)?;
+    holds().save(deps.storage, id, &Hold { account: info.sender.clone(), merchant: merchant.clone(), amount, expiry })?;
+
+    Ok(Response::new()
+        .add_attribute("action", "authorize_hold")
+        .add_attribute("hold_id", id.to_string())
+        .add_attribute("account", info.sender)
+        .add_attribute("merchant", merchant)
+        .add_attribute("amount", amount))
+}
+
+pub fn execute_capture_hold(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+    amount: Uint128,
+) -> Result<Response, ContractError> {
+    let hold = holds().load(deps.storage, id)?;
+    if info.sender != hold.merchant {
+        return Err(ContractError::Unauthorized {});
+    }
+    if env.block.time >= hold.expiry {
+        return Err(ContractError::HoldExpired {});
+    }
+    if amount > hold.amount {
+        return Err(ContractError::CaptureExceedsHold { amount: hold.amount });
+    }
+
+    // The hold goes first, so the captured funds are no longer reserved when they are debited.
+    holds().remove(deps.storage, id)?;
+    let receipt = transfer_between(deps.storage, &env, hold.account, hold.merchant, amount, TransferNote::default())?;
+
+    Ok(Response::new()
+        .add_attribute("action", "capture_hold")
+        .add_attribute("hold_id", id.to_string())
+        .add_attribute("amount", amount)
+        .add_attribute("released", hold.amount - amount)
+        .add_attributes(transfer_attributes(receipt.transfer_id, TransferNote::default()))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+pub fn execute_release_hold(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let hold = holds().load(deps.storage, id)?;
+    if info.sender != hold.merchant && env.block.time < hold.expiry {
+        return Err(ContractError::HoldNotExpired {});
+    }
+
+    holds().remove(deps.storage, id)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "release_hold")
+        .add_attribute("hold_id", id.to_string())
+        .add_attribute("amount", hold.amount))
+}
+
+fn hold_response(id: u64, hold: Hold) -> HoldResponse {
+    HoldResponse {
+        id,
+        account: hold.account,
+        merchant: hold.merchant,
+        amount: hold.amount,
+        expiry: hold.expiry,
+    }
+}
+
+fn get_holds_by_account_resolver(deps: Deps, env: Env, account: Addr, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let holds = holds().idx.account.prefix(account)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .filter(|item| item.as_ref().map_or(true, |(_, hold)| hold.expiry > env.block.time))
+        .take(limit)
+        .map(|item| item.map(|(id, hold)| hold_response(id, hold)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&HoldsResponse { holds })
+}
+
+fn get_holds_by_merchant_resolver(deps: Deps, env: Env, merchant: Addr, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let holds = holds().idx.merchant.prefix(merchant)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .filter(|item| item.as_ref().map_or(true, |(_, hold)| hold.expiry > env.block.time))
+        .take(limit)
+        .map(|item| item.map(|(id, hold)| hold_response(id, hold)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&HoldsResponse { holds })
+}
+
+pub fn execute_create_campaign(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    target: Uint128,
+    deadline: Timestamp,
+    split: Vec<SplitMember>,
+) -> Result<Response, ContractError> {
+    if target.is_zero() || deadline <= env.block.time {
+        return Err(ContractError::InvalidCampaign {});
+    }
+    if !split.is_empty() {
+        validate_split_members(deps.as_ref(), &split)?;
+    }
+
+    let id = CAMPAIGN_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    CAMPAIGN_COUNT.save(deps.storage, &id)?;
+    let campaign = Campaign {
+        creator: info.sender.clone(),
+        target,
+        deadline,
+        split,
+        raised: Uint128::zero(),
+        status: CampaignStatus::Open,
+    };
+    CAMPAIGNS.save(deps.storage, id, &campaign)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_campaign")
+        .add_attribute("campaign_id", id.to_string())
+        .add_attribute("creator", info.sender)
+        .add_attribute("target", target))
+}
+
+pub fn execute_contribute(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let funds = one_coin(&info)?;
+    if funds.denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom: funds.denom });
+    }
+    let mut campaign = CAMPAIGNS.load(deps.storage, id)?;
+    if campaign.status != CampaignStatus::Open || env.block.time >= campaign.deadline {
+        return Err(ContractError::CampaignClosed {});
+    }
+
+    campaign.raised = campaign.raised.checked_add(funds.amount).map_err(StdError::from)?;
+    CAMPAIGNS.save(deps.storage, id, &campaign)?;
+    let key = (id, info.sender.clone());
+    let mut pledge = pledges().may_load(deps.storage, key.clone())?
+        .unwrap_or(Pledge { contributor: info.sender.clone(), amount: Uint128::zero(), refunded: false });
+    pledge.amount = pledge.amount.checked_add(funds.amount).map_err(StdError::from)?;
+    pledges().save(deps.storage, key, &pledge)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "contribute")
+        .add_attribute("campaign_id", id.to_string())
+        .add_attribute("contributor", info.sender)
+        .add_attribute("amount", funds.amount)
+        .add_attribute("raised", campaign.raised))
+}
+
+// A funded campaign is paid out like TransferToSplitGroup, with the contract as the sender.
+pub fn execute_finalize_campaign(
+    deps: DepsMut,
+    env: Env,
+    _info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut campaign = CAMPAIGNS.load(deps.storage, id)?;
+    if campaign.status != CampaignStatus::Open {
+        return Err(ContractError::CampaignFinalized {});
+    }
+    if env.block.time < campaign.deadline {
+        return Err(ContractError::CampaignNotEnded {});
+    }
+
+    if campaign.raised < campaign.target {
+        campaign.status = CampaignStatus::Failed;
+        CAMPAIGNS.save(deps.storage, id, &campaign)?;
+        return Ok(Response::new()
+            .add_attribute("action", "finalize_campaign")
+            .add_attribute("campaign_id", id.to_string())
+            .add_attribute("status", "failed"));
+    }
+
+    let members = if campaign.split.is_empty() {
+        vec![(campaign.creator.clone(), 1)]
+    } else {
+        campaign.split.iter().filter(|m| m.weight > 0).map(|m| (m.address.clone(), m.weight)).collect()
+    };
+    // The payout is recorded as sent by the creator, so the contract never shows up as a sender in records or stats.
+    let receipt = credit_by_weight(deps.storage, &env, &campaign.creator, campaign.raised, members, TransferNote::default())?;
+    campaign.status = CampaignStatus::Funded;
+    CAMPAIGNS.save(deps.storage, id, &campaign)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "finalize_campaign")
+        .add_attribute("campaign_id", id.to_string())
+        .add_attribute("status", "funded")
+        .add_attribute("amount", campaign.raised)
+        .add_attributes(transfer_attributes(receipt.transfer_id, TransferNote::default()))
+        .set_data(save_receipt(deps.storage, &receipt)?))
+}
+
+// Refunds can be claimed as soon as the deadline has passed short of the target, without waiting for FinalizeCampaign.
+pub fn execute_claim_refund(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let mut campaign = CAMPAIGNS.load(deps.storage, id)?;
+    if campaign.status == CampaignStatus::Open {
+        if env.block.time < campaign.deadline {
+            return Err(ContractError::CampaignNotEnded {});
+        }
+        if campaign.raised < campaign.target {
+            campaign.status = CampaignStatus::Failed;
+            CAMPAIGNS.save(deps.storage, id, &campaign)?;
+        }
+    }
+    if campaign.status != CampaignStatus::Failed {
+        return Err(ContractError::CampaignNotFailed {});
+    }
+
+    let key = (id, info.sender.clone());
+    let mut pledge = match pledges().may_load(deps.storage, key.clone())? {
+        Some(pledge) if !pledge.refunded => pledge,
+        _ => return Err(ContractError::NoPledge {}),
+    };
+    pledge.refunded = true;
+    pledges().save(deps.storage, key, &pledge)?;
+
+    Ok(send_tokens(info.sender, vec![Coin { denom: "usei".to_string(), amount: pledge.amount }], "claim_refund")
+        .add_attribute("campaign_id", id.to_string())
+        .add_attribute("amount", pledge.amount))
+}
+
+fn campaign_response(id: u64, campaign: Campaign) -> CampaignResponse {
+    CampaignResponse {
+        id,
+        creator: campaign.creator,
+        target: campaign.target,
+        deadline: campaign.deadline,
+        split: campaign.split,
+        raised: campaign.raised,
+        status: campaign.status,
+    }
+}
+
+fn pledge_response(campaign_id: u64, pledge: Pledge) -> PledgeResponse {
+    PledgeResponse {
+        campaign_id,
+        contributor: pledge.contributor,
+        amount: pledge.amount,
+        refunded: pledge.refunded,
+    }
+}
+
+fn get_campaigns_resolver(deps: Deps, _env: Env, start_after: Option<u64>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let campaigns = CAMPAIGNS.range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, campaign)| campaign_response(id, campaign)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&CampaignsResponse { campaigns })
+}
+
+fn get_campaign_pledges_resolver(deps: Deps, _env: Env, id: u64, start_after: Option<Addr>, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let pledges = pledges().prefix(id)
+        .range(deps.storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, pledge)| pledge_response(id, pledge)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&PledgesResponse { pledges })
+}
+
+fn get_contributor_pledges_resolver(
+    deps: Deps,
+    _env: Env,
+    contributor: Addr,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let min = start_after.map(|id| Bound::exclusive((id, contributor.clone())));
+    let pledges = pledges().idx.contributor.prefix(contributor)
+        .range(deps.storage, min, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|((id, _), pledge)| pledge_response(id, pledge)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&PledgesResponse { pledges })
+}
+
+fn keeper_bounty(storage: &dyn Storage) -> StdResult<Uint128> {
+    Ok(KEEPER_BOUNTY.may_load(storage)?.unwrap_or(Uint128::from(DEFAULT_KEEPER_BOUNTY)))
+}
+
+pub fn execute_update_keeper_bounty(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    bounty: Uint128,
+) -> Result<Response, ContractError> {
+    let config = CONFIG.load(deps.storage)?;
+    if info.sender != config.owner {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    KEEPER_BOUNTY.save(deps.storage, &bounty)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "update_keeper_bounty")
+        .add_attribute("bounty", bounty))
+}
+
+pub fn execute_create_standing_order(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    recipients: Vec<SplitMember>,
+    amount: Uint128,
+    interval: u64,
+    end: Timestamp,
+) -> Result<Response, ContractError> {
+    let deposit = one_coin(&info)?;
+    if deposit.denom != "usei" {
+        return Err(ContractError::UnsupportedDenom { denom: deposit.denom });
+    }
+    validate_split_members(deps.as_ref(), &recipients)?;
+    if interval > MAX_STANDING_ORDER_INTERVAL {
+        return Err(ContractError::InvalidStandingOrderInterval { max: MAX_STANDING_ORDER_INTERVAL });
+    }
+    let next_payment = env.block.time.plus_seconds(interval);
+    let bounty = keeper_bounty(deps.storage)?;
+    let per_period = amount.checked_add(bounty).map_err(StdError::from)?;
+    if amount.is_zero() || interval == 0 || next_payment > end || deposit.amount < per_period {
+        return Err(ContractError::InvalidStandingOrder {});
+    }
+
+    let id = STANDING_ORDER_COUNT.may_load(deps.storage)?.unwrap_or_default() + 1;
+    STANDING_ORDER_COUNT.save(deps.storage, &id)?;
+    let order = StandingOrder {
+        payer: info.sender.clone(),
+        recipients,
+        amount,
+        interval,
+        end,
+        next_payment,
+        deposit: deposit.amount,
+        bounty,
+    };
+    standing_orders().save(deps.storage, id, &order)?;
+
+    Ok(Response::new()
+        .add_attribute("action", "create_standing_order")
+        .add_attribute("standing_order_id", id.to_string())
+        .add_attribute("payer", info.sender)
+        .add_attribute("deposit", deposit.amount))
+}
+
+pub fn execute_cancel_standing_order(
+    deps: DepsMut,
+    _env: Env,
+    info: MessageInfo,
+    id: u64,
+) -> Result<Response, ContractError> {
+    let order = standing_orders().load(deps.storage, id)?;
+    if info.sender != order.payer {
+        return Err(ContractError::Unauthorized {});
+    }
+
+    standing_orders().remove(deps.storage, id)?;
+
+    let res = if order.deposit.is_zero() {
+        Response::new().add_attribute("action", "cancel_standing_order")
+    } else {
+        send_tokens(order.payer, vec![Coin { denom: "usei".to_string(), amount: order.deposit }], "cancel_standing_order")
+    };
+    Ok(res
+        .add_attribute("standing_order_id", id.to_string())
+        .add_attribute("refund", order.deposit))
+}
+
+// Each payment due is split like TransferToSplitGroup and earns the sender the keeper bounty, both out of the order's
+// deposit.  Missed periods are caught up on, as far as the deposit allows.
+pub fn execute_execute_due(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    ids: Vec<u64>,
+) -> Result<Response, ContractError> {
+    let mut payments = 0u64;
+    let mut bounties = Uint128::zero();
+    let mut transfer_ids = vec![];
+    let mut ended = vec![];
+    let mut res = Response::new().add_attribute("action", "execute_due");
+
+    for id in ids {
+        let mut order = match standing_orders().may_load(deps.storage, id)? {
+            Some(order) if order.next_payment <= env.block.time => order,
+            _ => continue,
+        };
+        let per_period = order.amount.checked_add(order.bounty).map_err(StdError::from)?;
+        let members = order.recipients.iter()
+            .filter(|m| m.weight > 0)
+            .map(|m| (m.address.clone(), m.weight))
+            .collect::<Vec<_>>();
+
+        // Bounded, so that an order that fell far behind cannot make the call run out of gas.  It stays due.
+        let mut periods = 0;
+        while periods < MAX_PERIODS_PER_EXECUTION
+            && order.next_payment <= env.block.time
+            && order.next_payment <= order.end
+            && order.deposit >= per_period
+        {
+            let mut receipt = credit_by_weight(deps.storage, &env, &order.payer, order.amount, members.clone(), TransferNote::default())?;
+            if !order.bounty.is_zero() {
+                credit_recipient(deps.storage, &env, &order.payer, info.sender.clone(), order.bounty)?;
+            }
+            receipt.fee = order.bounty;
+            save_receipt(deps.storage, &receipt)?;
+            transfer_ids.push(receipt.transfer_id.to_string());
+            bounties += order.bounty;
+            order.deposit -= per_period;
+            order.next_payment = order.next_payment.plus_seconds(order.interval);
+            payments += 1;
+            periods += 1;
+        }
+
+        if order.next_payment > order.end || order.deposit < per_period {
+            standing_orders().remove(deps.storage, id)?;
+            if !order.deposit.is_zero() {
+                res = res.add_message(BankMsg::Send {
+                    to_address: order.payer.to_string(),
+                    amount: vec![Coin { denom: "usei".to_string(), amount: order.deposit }],
+                });
+            }
+            ended.push(id.to_string());
+        } else {
+            standing_orders().save(deps.storage, id, &order)?;
+        }
+    }
+
+    if payments == 0 && ended.is_empty() {
+        return Err(ContractError::NothingDue {});
+    }
+    Ok(res
+        .add_attribute("payments", payments.to_string())
+        .add_attribute("transfer_ids", transfer_ids.join(","))
+        .add_attribute("bounty", bounties)
+        .add_attribute("ended", ended.join(",")))
+}
+
+fn standing_order_response(id: u64, order: StandingOrder) -> StandingOrderResponse {
+    StandingOrderResponse {
+        id,
+        payer: order.payer,
+        recipients: order.recipients,
+        amount: order.amount,
+        interval: order.interval,
+        end: order.end,
+        next_payment: order.next_payment,
+        deposit: order.deposit,
+        bounty: order.bounty,
+    }
+}
+
+fn get_due_standing_orders_resolver(deps: Deps, env: Env, limit: Option<u32>) -> StdResult<Binary> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let max = Bound::inclusive((env.block.time.seconds(), u64::MAX));
+    let orders = standing_orders().idx.next_payment
+        .range(deps.storage, None, Some(max), Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(id, order)| standing_order_response(id, order)))
+        .collect::<StdResult<_>>()?;
+    to_json_binary(&StandingOrdersResponse { orders })
 }
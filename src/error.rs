@@ -1,4 +1,4 @@
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Coin, Decimal, StdError, Uint128};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,4 +11,220 @@ pub enum ContractError {
 
     #[error("Insufficient balance to meet the requested withdrawal amount")]
     InsufficientBalanceForWithdraw {},
+
+    #[error("Unauthorized")]
+    Unauthorized {},
+
+    #[error("Escrow must contain at least one milestone with a non-zero amount")]
+    InvalidMilestones {},
+
+    #[error("Escrow has no pending milestones")]
+    NoPendingMilestones {},
+
+    #[error("Escrow cannot be cancelled under its cancel policy")]
+    CancelNotAllowed {},
+
+    #[error("Exactly one non-zero coin must be sent")]
+    InvalidFunds {},
+
+    #[error("Hash must be a 32 byte SHA-256 digest")]
+    InvalidHash {},
+
+    #[error("Preimage does not match the HTLC hash")]
+    InvalidPreimage {},
+
+    #[error("Expiry must be in the future")]
+    InvalidExpiry {},
+
+    #[error("HTLC has expired")]
+    HtlcExpired {},
+
+    #[error("HTLC has not expired yet")]
+    HtlcNotExpired {},
+
+    #[error("HTLC is no longer locked")]
+    HtlcNotLocked {},
+
+    #[error("Insufficient balance")]
+    InsufficientBalance {},
+
+    #[error("Unsupported denom: {denom}")]
+    UnsupportedDenom { denom: String },
+
+    #[error("Public key must be a compressed or uncompressed secp256k1 key")]
+    InvalidPubkey {},
+
+    #[error("No public key registered for {address}")]
+    PubkeyNotRegistered { address: String },
+
+    #[error("Invalid signature")]
+    InvalidSignature {},
+
+    #[error("Cheque has expired")]
+    ChequeExpired {},
+
+    #[error("Cheque nonce has already been used")]
+    NonceAlreadyUsed {},
+
+    #[error("Invalid relay nonce: expected {expected}")]
+    InvalidRelayNonce { expected: u64 },
+
+    #[error("Message cannot be relayed")]
+    RelayNotAllowed {},
+
+    #[error("Channel is not open for this operation")]
+    ChannelNotOpen {},
+
+    #[error("Voucher amount exceeds the channel deposit")]
+    VoucherExceedsDeposit {},
+
+    #[error("Challenge period must be at most {max} seconds")]
+    InvalidChallengePeriod { max: u64 },
+
+    #[error("Voucher amount must be higher than the amount already claimed")]
+    VoucherNotHigher {},
+
+    #[error("Challenge period is still running")]
+    ChallengePeriodActive {},
+
+    #[error("Challenge period is over")]
+    ChallengePeriodOver {},
+
+    #[error("CW20 token {token} is not whitelisted")]
+    Cw20NotWhitelisted { token: String },
+
+    #[error("IBC channel {channel_id} is not allowed")]
+    IbcChannelNotAllowed { channel_id: String },
+
+    #[error("Unknown reply id: {id}")]
+    UnknownReplyId { id: u64 },
+
+    #[error("Only unordered ledger channels are supported")]
+    InvalidIbcOrder {},
+
+    #[error("Invalid ledger channel version: {version}")]
+    InvalidIbcVersion { version: String },
+
+    #[error("Unknown ledger channel: {channel_id}")]
+    UnknownLedgerChannel { channel_id: String },
+
+    #[error("Not enough usei escrowed for ledger channel {channel_id}")]
+    InsufficientLedgerEscrow { channel_id: String },
+
+    #[error("Ledger counterparty {port_id} on {connection_id} is not trusted")]
+    UntrustedLedgerCounterparty { connection_id: String, port_id: String },
+
+    #[error("No swap pair configured for {offer_denom} to {ask_denom}")]
+    SwapPairNotFound { offer_denom: String, ask_denom: String },
+
+    #[error("Swap returned {received}, less than the minimum of {min_out}")]
+    SwapBelowMinimum { received: Uint128, min_out: Uint128 },
+
+    #[error("No price oracle configured")]
+    OracleNotConfigured {},
+
+    #[error("Oracle price is older than {max_price_age} seconds")]
+    PriceTooOld { max_price_age: u64 },
+
+    #[error("Oracle price {price} is outside the slippage tolerance")]
+    MaxSlippageExceeded { price: Decimal },
+
+    #[error("Staking is not configured")]
+    StakingNotConfigured {},
+
+    #[error("Buffer ratio must not exceed 1")]
+    InvalidBufferRatio {},
+
+    #[error("Not enough liquid usei, try again after the next rebalance")]
+    InsufficientLiquidity {},
+
+    #[error("No unbonded withdrawals to claim")]
+    NothingToClaim {},
+
+    #[error("No fees to claim")]
+    NoFeesToClaim {},
+
+    #[error("Group has no members with weight")]
+    EmptyGroup {},
+
+    #[error("Group {group} is not tracked by this contract, or has dropped its hook")]
+    GroupNotTracked { group: String },
+
+    #[error("Group membership is only recorded after height {since}")]
+    GroupHistoryUnavailable { since: u64 },
+
+    #[error("Split group members must be distinct and have a positive total weight")]
+    InvalidSplitGroup {},
+
+    #[error("Memo must be at most {max} bytes")]
+    MemoTooLong { max: usize },
+
+    #[error("Reference must be between 1 and {max} bytes")]
+    InvalidReference { max: usize },
+
+    #[error("Idempotency key must be between 1 and {max} bytes")]
+    InvalidIdempotencyKey { max: usize },
+
+    #[error("Idempotency key {key} was already used with different parameters")]
+    IdempotencyKeyReused { key: String },
+
+    #[error("Invoice id must be between 1 and {max} bytes")]
+    InvalidInvoiceId { max: usize },
+
+    #[error("Invoice {id} already exists")]
+    InvoiceExists { id: String },
+
+    #[error("Invoice must have a positive amount and a future expiry")]
+    InvalidInvoice {},
+
+    #[error("Invoice is not open")]
+    InvoiceNotOpen {},
+
+    #[error("Invoice has expired")]
+    InvoiceExpired {},
+
+    #[error("Invoice has not expired yet")]
+    InvoiceNotExpired {},
+
+    #[error("Invoice must be paid with exactly {expected}")]
+    InvoicePaymentMismatch { expected: Coin },
+
+    #[error("Funds are on hold")]
+    FundsOnHold {},
+
+    #[error("Hold has expired")]
+    HoldExpired {},
+
+    #[error("Hold has not expired yet")]
+    HoldNotExpired {},
+
+    #[error("Cannot capture more than the held {amount}")]
+    CaptureExceedsHold { amount: Uint128 },
+
+    #[error("Campaign must have a positive target and a future deadline")]
+    InvalidCampaign {},
+
+    #[error("Campaign is not accepting contributions")]
+    CampaignClosed {},
+
+    #[error("Campaign deadline has not passed yet")]
+    CampaignNotEnded {},
+
+    #[error("Campaign has already been finalized")]
+    CampaignFinalized {},
+
+    #[error("Campaign did not fail, so there is nothing to refund")]
+    CampaignNotFailed {},
+
+    #[error("No pledge to refund")]
+    NoPledge {},
+
+    #[error("Standing order needs a positive amount and interval, a payment before its end, and a deposit for one period")]
+    InvalidStandingOrder {},
+
+    #[error("Standing order interval must be at most {max} seconds")]
+    InvalidStandingOrderInterval { max: u64 },
+
+    #[error("None of the standing orders are due")]
+    NothingDue {},
 }
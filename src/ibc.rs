@@ -0,0 +1,180 @@
+use cosmwasm_std::{
+    entry_point, from_json, to_json_binary, Addr, Binary, DepsMut, Env, Ibc3ChannelOpenResponse, IbcBasicResponse, IbcChannel,
+    IbcChannelCloseMsg, IbcChannelConnectMsg, IbcChannelOpenMsg, IbcChannelOpenResponse, IbcOrder, IbcPacket, IbcPacketAckMsg,
+    IbcPacketReceiveMsg, IbcPacketTimeoutMsg, IbcReceiveResponse, StdError, StdResult, Storage, Uint128
+};
+
+use crate::contract::{balance_of, check_ledger_channel, check_ledger_counterparty, credit_balance};
+use crate::error::ContractError;
+use crate::msg::{GetBalanceResponse, LedgerAck, LedgerCredit, LedgerPacket};
+use crate::state::{EntryKind, LedgerCounterparty, LEDGER_CHANNELS, LEDGER_ESCROW, LEDGER_VOUCHERS, REMOTE_BALANCES};
+
+// Version negotiated in the channel handshake.  Bump it whenever LedgerPacket or LedgerAck change incompatibly.
+pub const IBC_APP_VERSION: &str = "ledger-1";
+
+// Anyone can open a channel with the right version, so the counterparty must also be one the owner trusts.
+fn check_channel(storage: &dyn Storage, channel: &IbcChannel, counterparty_version: Option<&str>) -> Result<(), ContractError> {
+    if channel.order != IbcOrder::Unordered {
+        return Err(ContractError::InvalidIbcOrder {});
+    }
+    if channel.version != IBC_APP_VERSION {
+        return Err(ContractError::InvalidIbcVersion { version: channel.version.clone() });
+    }
+    if let Some(version) = counterparty_version {
+        if version != IBC_APP_VERSION {
+            return Err(ContractError::InvalidIbcVersion { version: version.to_string() });
+        }
+    }
+    check_ledger_counterparty(storage, &counterparty_of(channel))
+}
+
+fn counterparty_of(channel: &IbcChannel) -> LedgerCounterparty {
+    LedgerCounterparty {
+        connection_id: channel.connection_id.clone(),
+        port_id: channel.counterparty_endpoint.port_id.clone(),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_open(deps: DepsMut, _env: Env, msg: IbcChannelOpenMsg) -> Result<IbcChannelOpenResponse, ContractError> {
+    check_channel(deps.storage, msg.channel(), msg.counterparty_version())?;
+    Ok(Some(Ibc3ChannelOpenResponse { version: IBC_APP_VERSION.to_string() }))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_connect(deps: DepsMut, _env: Env, msg: IbcChannelConnectMsg) -> Result<IbcBasicResponse, ContractError> {
+    check_channel(deps.storage, msg.channel(), msg.counterparty_version())?;
+    let channel = msg.channel();
+    LEDGER_CHANNELS.save(deps.storage, channel.endpoint.channel_id.clone(), &counterparty_of(channel))?;
+
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_connect")
+        .add_attribute("channel_id", &channel.endpoint.channel_id))
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_channel_close(deps: DepsMut, _env: Env, msg: IbcChannelCloseMsg) -> Result<IbcBasicResponse, ContractError> {
+    let channel = msg.channel();
+    LEDGER_CHANNELS.remove(deps.storage, channel.endpoint.channel_id.clone());
+
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ibc_close")
+        .add_attribute("channel_id", &channel.endpoint.channel_id))
+}
+
+// Errors are returned rather than acknowledged here, so that the chain discards whatever a packet wrote before it
+// failed.  wasmd acknowledges them with `{"error":"..."}`, which the sister deployment reads as a LedgerAck::Error and
+// refunds.
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_receive(deps: DepsMut, env: Env, msg: IbcPacketReceiveMsg) -> Result<IbcReceiveResponse, ContractError> {
+    let result = receive_ledger_packet(deps, &env, &msg.packet)?;
+
+    Ok(IbcReceiveResponse::new()
+        .set_ack(to_json_binary(&LedgerAck::Result(result))?)
+        .add_attribute("action", "ibc_receive")
+        .add_attribute("channel_id", &msg.packet.dest.channel_id))
+}
+
+fn receive_ledger_packet(deps: DepsMut, env: &Env, packet: &IbcPacket) -> Result<Binary, ContractError> {
+    let channel_id = packet.dest.channel_id.clone();
+    check_ledger_channel(deps.storage, &channel_id)?;
+    match from_json(&packet.data)? {
+        LedgerPacket::Credit { credits, .. } => {
+            // The usei behind these credits is escrowed on the other side, so they are held as vouchers.
+            for (address, amount) in validate_credits(&deps, credits)? {
+                credit_vouchers(deps.storage, &channel_id, address, amount)?;
+            }
+            Ok(to_json_binary(&true)?)
+        }
+        LedgerPacket::Return { credits, .. } => {
+            let credits = validate_credits(&deps, credits)?;
+            // Returned vouchers are paid out of what was escrowed for this channel, and never more.
+            let total = credits.iter().try_fold(Uint128::zero(), |acc, (_, amount)| acc.checked_add(*amount))
+                .map_err(StdError::from)?;
+            let escrow = LEDGER_ESCROW.may_load(deps.storage, channel_id.clone())?.unwrap_or_default();
+            let remaining = escrow.checked_sub(total)
+                .map_err(|_| ContractError::InsufficientLedgerEscrow { channel_id: channel_id.clone() })?;
+            LEDGER_ESCROW.save(deps.storage, channel_id, &remaining)?;
+            for (address, amount) in credits {
+                credit_balance(deps.storage, env, address, amount, EntryKind::LedgerReturn, None)?;
+            }
+            Ok(to_json_binary(&true)?)
+        }
+        LedgerPacket::Balance { address } => {
+            let balance = balance_of(deps.storage, &Addr::unchecked(address))?;
+            Ok(to_json_binary(&GetBalanceResponse { balance })?)
+        }
+    }
+}
+
+// Validate every account before crediting any of them.
+fn validate_credits(deps: &DepsMut, credits: Vec<LedgerCredit>) -> Result<Vec<(Addr, Uint128)>, StdError> {
+    credits.into_iter()
+        .map(|c| Ok((deps.api.addr_validate(&c.address)?, c.amount)))
+        .collect()
+}
+
+fn credit_vouchers(storage: &mut dyn Storage, channel_id: &str, address: Addr, amount: Uint128) -> StdResult<Uint128> {
+    LEDGER_VOUCHERS.update(storage, (channel_id.to_string(), address), |vouchers| -> StdResult<_> {
+        Ok(vouchers.unwrap_or_default().checked_add(amount)?)
+    })
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_ack(deps: DepsMut, env: Env, msg: IbcPacketAckMsg) -> Result<IbcBasicResponse, ContractError> {
+    let packet = msg.original_packet;
+    let ack: LedgerAck = from_json(&msg.acknowledgement.data)?;
+
+    match (from_json(&packet.data)?, ack) {
+        (LedgerPacket::Credit { sender, credits }, LedgerAck::Error(err)) => {
+            refund_credits(deps, &env, &packet.src.channel_id, sender, credits.iter().map(|c| c.amount).sum())
+                .map(|res| res.add_attribute("error", err))
+        }
+        (LedgerPacket::Return { sender, credits }, LedgerAck::Error(err)) => {
+            refund_vouchers(deps, &packet.src.channel_id, sender, credits.iter().map(|c| c.amount).sum())
+                .map(|res| res.add_attribute("error", err))
+        }
+        (LedgerPacket::Credit { .. } | LedgerPacket::Return { .. }, LedgerAck::Result(_)) => {
+            Ok(IbcBasicResponse::new().add_attribute("action", "ledger_credits_acknowledged"))
+        }
+        (LedgerPacket::Balance { address }, LedgerAck::Result(result)) => {
+            let resp: GetBalanceResponse = from_json(&result)?;
+            REMOTE_BALANCES.save(deps.storage, (packet.src.channel_id, address.clone()), &resp.balance)?;
+            Ok(IbcBasicResponse::new()
+                .add_attribute("action", "remote_balance")
+                .add_attribute("address", address)
+                .add_attribute("balance", resp.balance))
+        }
+        (LedgerPacket::Balance { .. }, LedgerAck::Error(err)) => Ok(IbcBasicResponse::new().add_attribute("error", err)),
+    }
+}
+
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn ibc_packet_timeout(deps: DepsMut, env: Env, msg: IbcPacketTimeoutMsg) -> Result<IbcBasicResponse, ContractError> {
+    let channel_id = &msg.packet.src.channel_id;
+    match from_json(&msg.packet.data)? {
+        LedgerPacket::Credit { sender, credits } => refund_credits(deps, &env, channel_id, sender, credits.iter().map(|c| c.amount).sum()),
+        LedgerPacket::Return { sender, credits } => refund_vouchers(deps, channel_id, sender, credits.iter().map(|c| c.amount).sum()),
+        LedgerPacket::Balance { .. } => Ok(IbcBasicResponse::new().add_attribute("action", "ibc_timeout")),
+    }
+}
+
+// Credits that never arrived are taken back out of the channel's escrow and returned to the sender.
+fn refund_credits(deps: DepsMut, env: &Env, channel_id: &str, sender: String, amount: Uint128) -> Result<IbcBasicResponse, ContractError> {
+    LEDGER_ESCROW.update(deps.storage, channel_id.to_string(), |escrow| -> StdResult<_> {
+        Ok(escrow.unwrap_or_default().checked_sub(amount)?)
+    })?;
+    credit_balance(deps.storage, env, Addr::unchecked(&sender), amount, EntryKind::Refund, None)?;
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ledger_credits_refund")
+        .add_attribute("to", sender)
+        .add_attribute("amount", amount))
+}
+
+fn refund_vouchers(deps: DepsMut, channel_id: &str, sender: String, amount: Uint128) -> Result<IbcBasicResponse, ContractError> {
+    credit_vouchers(deps.storage, channel_id, Addr::unchecked(&sender), amount)?;
+    Ok(IbcBasicResponse::new()
+        .add_attribute("action", "ledger_vouchers_refund")
+        .add_attribute("to", sender)
+        .add_attribute("amount", amount))
+}
@@ -1,5 +1,6 @@
 pub mod contract;
 mod error;
+pub mod ibc;
 pub mod msg;
 pub mod state;
 
@@ -1,18 +1,292 @@
-use crate::state::Config;
+use crate::state::{CampaignStatus, CancelPolicy, ChannelStatus, Cheque, Config, EntryKind, HtlcStatus, IdempotencyRecord, InvoiceStatus, LedgerCounterparty, TransferReceipt, MilestoneStatus, OracleConfig, SplitMember, StakingConfig, UnbondingWithdrawal};
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::{Addr, Uint128};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, HexBinary, Order, Timestamp, Uint128};
+use cw20::Cw20ReceiveMsg;
+use cw4::MemberChangedHookMsg;
 
 #[cw_serde]
 pub struct InstantiateMsg {}
 
+#[cw_serde]
+pub struct MigrateMsg {}
+
 #[cw_serde]
 pub struct GetOwner {}
 
 #[cw_serde]
 pub enum ExecuteMsg {
-    Transfer { address1: Addr, address2: Addr, amount: Uint128 },
+    // Transfers take an optional free-form `memo` and a client `reference`, such as an invoice id, that are stored
+    // with the transfer record and can be searched with TransfersByReference.
+    // Retrying any transfer with the same `idempotency_key` and parameters is a no-op that refunds the funds sent and
+    // returns the original transfer id.  Keys are scoped to the sender and remembered for the idempotency window.
+    Transfer {
+        address1: Addr,
+        address2: Addr,
+        amount: Uint128,
+        memo: Option<String>,
+        reference: Option<String>,
+        idempotency_key: Option<String>,
+    },
     Withdraw { amount: Uint128 },
-    TransferWithTip { address1: Addr, address2: Addr, amount: Uint128 },
+    TransferWithTip {
+        address1: Addr,
+        address2: Addr,
+        amount: Uint128,
+        memo: Option<String>,
+        reference: Option<String>,
+        idempotency_key: Option<String>,
+    },
+    // Escrow agreements pay a contractor in tranches.  The sum of the milestone amounts must be sent with the request.
+    CreateEscrow { contractor: Addr, milestones: Vec<MilestoneMsg>, cancel_policy: CancelPolicy },
+    // Releases the next pending milestone into the contractor's balance.
+    ReleaseMilestone { id: u64 },
+    // Returns all unreleased milestone amounts to the payer's balance.
+    CancelEscrow { id: u64 },
+    // Hash time-locked contracts lock the sent funds until the SHA-256 preimage of `hash` is revealed.
+    LockHtlc { recipient: Addr, hash: HexBinary, expiry: Timestamp },
+    ClaimHtlc { id: u64, preimage: HexBinary },
+    // Returns the locked funds to the sender once the HTLC has expired.
+    RefundHtlc { id: u64 },
+    // Registers the secp256k1 public key used to verify cheques drawn on the sender's balance.
+    RegisterPubkey { pubkey: HexBinary },
+    // Moves `cheque.amount` from the payer's balance to the payee's.  Anyone may submit a cheque.
+    RedeemCheque { cheque: Cheque, signature: HexBinary },
+    // Moves usei from the sender's ledger balance to the recipient's, without any bank transfer.
+    TransferBalance {
+        recipient: Addr,
+        amount: Uint128,
+        memo: Option<String>,
+        reference: Option<String>,
+        idempotency_key: Option<String>,
+    },
+    // Runs `msg` on behalf of the account derived from `signer_pubkey`, who signed the RelaySignDoc off-chain.
+    // Only ledger operations that need no funds can be relayed.
+    Relay { msg: Box<ExecuteMsg>, signer_pubkey: HexBinary, signature: HexBinary, nonce: u64 },
+    // Opens a unidirectional payment channel funded with the sent usei.  The payer must have a registered public key.
+    OpenChannel { payee: Addr, challenge_period: u64 },
+    // Presents the latest payer-signed ChannelVoucher.  The first call starts the challenge period,
+    // later calls during the challenge period must present a higher amount.
+    CloseChannel { id: u64, amount: Uint128, signature: HexBinary },
+    // Lets the payer start the challenge period, after which the unclaimed deposit can be reclaimed.
+    RequestChannelClose { id: u64 },
+    // Credits the claimed amount to the payee and the rest of the deposit to the payer once the challenge period is over.
+    SettleChannel { id: u64 },
+    // Hook called by a whitelisted CW20 contract.  `msg` must be a serialized ReceiveMsg.
+    Receive(Cw20ReceiveMsg),
+    // Pays out a CW20 ledger balance with a Cw20ExecuteMsg::Transfer.
+    WithdrawCw20 { token: Addr, amount: Uint128 },
+    // Owner only.
+    UpdateCw20Whitelist { add: Vec<Addr>, remove: Vec<Addr> },
+    // Debits usei from the sender's balance and sends it over ICS-20 to `remote_address`.
+    // `timeout` is in seconds from the current block time.
+    WithdrawIbc { channel_id: String, remote_address: String, amount: Uint128, timeout: u64 },
+    // Owner only.
+    UpdateIbcChannels { add: Vec<String>, remove: Vec<String> },
+    // Split instruction carried in the wasm memo of an incoming ICS-20 transfer (ibc-hooks).  The sender must be the
+    // intermediate account ibc-hooks derives for `original_sender` on `channel`, and all usei received is split.  Only
+    // usei returning from another chain arrives as usei.
+    IbcHookTransfer {
+        channel: String,
+        original_sender: String,
+        address1: Addr,
+        address2: Addr,
+        memo: Option<String>,
+        reference: Option<String>,
+    },
+    // Owner only.  Only trusted counterparties can open ledger channels, and packets from a channel whose
+    // counterparty is removed are rejected.
+    UpdateLedgerCounterparties { add: Vec<LedgerCounterparty>, remove: Vec<LedgerCounterparty> },
+    // Debits the sender and credits the given accounts on the sister deployment at the other end of a ledger channel.
+    // The usei stays escrowed here, and the accounts receive vouchers there that can only be returned.
+    // `timeout` is in seconds from the current block time.
+    SendLedgerCredits { channel_id: String, credits: Vec<LedgerCredit>, timeout: u64 },
+    // Sends vouchers received over `channel_id` back to the sister deployment, which releases the escrowed usei into
+    // the given accounts' balances.
+    ReturnLedgerCredits { channel_id: String, credits: Vec<LedgerCredit>, timeout: u64 },
+    // Asks the sister deployment for a balance.  The answer is cached and returned by QueryMsg::RemoteBalance.
+    QueryRemoteBalance { channel_id: String, address: String, timeout: u64 },
+    // Withdraws `amount` of `denom` from the sender's balance, swaps it through the configured pair and sends the
+    // proceeds to the sender.  Fails if fewer than `min_out` `ask_denom` are received.
+    WithdrawAndSwap { amount: Uint128, denom: String, ask_denom: String, min_out: Uint128 },
+    // Owner only.  Sets or, with `pair: None`, removes the pair contract used to swap `offer_denom` into `ask_denom`.
+    UpdateSwapPair { offer_denom: String, ask_denom: String, pair: Option<Addr> },
+    // Like Transfer and TransferWithTip, but `usd_amount` (in micro-USD) is converted to usei at the oracle price.
+    // The price must be within `max_slippage` of `belief_price`, and any usei sent beyond what is needed is refunded.
+    TransferUsd {
+        address1: Addr,
+        address2: Addr,
+        usd_amount: Uint128,
+        belief_price: Decimal,
+        max_slippage: Decimal,
+        memo: Option<String>,
+        reference: Option<String>,
+        idempotency_key: Option<String>,
+    },
+    TransferUsdWithTip {
+        address1: Addr,
+        address2: Addr,
+        usd_amount: Uint128,
+        belief_price: Decimal,
+        max_slippage: Decimal,
+        memo: Option<String>,
+        reference: Option<String>,
+        idempotency_key: Option<String>,
+    },
+    // Owner only.  `max_price_age` is in seconds.
+    UpdateOracle { oracle: Addr, max_price_age: u64 },
+    // Owner only.  Enables staking of idle usei, keeping `buffer_ratio` of the ledger liquid.  Validators that are
+    // removed are redelegated away from on the next Rebalance.
+    UpdateStaking { validators: Vec<String>, buffer_ratio: Decimal, unbonding_period: u64 },
+    // Claims staking rewards, shares them among account holders and delegates usei above the buffer.  Anyone may call it.
+    Rebalance {},
+    // Pays out the sender's withdrawals whose unbonding period is over.
+    ClaimWithdrawal {},
+    // Owner only.  Sets the share counts of the given fee revenue shareholders; zero shares removes a shareholder.
+    // Fees earned before the change stay claimable.
+    UpdateShareholders { shareholders: Vec<ShareholderMsg> },
+    // Pays out the fee revenue the sender has earned as a shareholder.
+    ClaimFees {},
+    // Splits the sent usei among the members of a cw4 group in proportion to their weights.  With `at_height`, the
+    // members and weights recorded at that height are used, which needs the group to be tracked since before it.
+    TransferToGroup { group_contract: Addr, at_height: Option<u64>, memo: Option<String>, reference: Option<String> },
+    // Starts recording the membership of a cw4 group that has this contract registered as a hook.
+    TrackGroup { group_contract: Addr },
+    // Called by a tracked cw4 group whenever its membership changes.
+    MemberChangedHook(MemberChangedHookMsg),
+    // Stores a named recipient list with the sender as its admin.
+    CreateSplitGroup { name: String, members: Vec<SplitMember> },
+    // Admin only.  Replaces the members, starting a new version of the group.
+    UpdateSplitGroup { id: u64, members: Vec<SplitMember> },
+    // Splits the sent usei among the members of a split group by weight, in the same way as TransferToGroup.
+    TransferToSplitGroup { id: u64, memo: Option<String>, reference: Option<String> },
+    // Owner only.  Number of blocks idempotency keys are remembered for.
+    UpdateIdempotencyWindow { blocks: u64 },
+    // Requests `amount` from `payer`, or from anyone if unset, to be split among `split` by weight when paid.  With an
+    // empty split the payee gets it all.  Ids are per payee and are used as the payment's reference, so they have the
+    // same limits.
+    CreateInvoice { id: String, amount: Uint128, denom: String, payer: Option<Addr>, expiry: Timestamp, split: Vec<SplitMember> },
+    // Pays an open invoice with exactly its amount.
+    PayInvoice { payee: Addr, id: String },
+    // Withdraws one of the sender's open invoices.
+    CancelInvoice { id: String },
+    // Closes an open invoice once it is past its expiry.  Anyone may call it.
+    ExpireInvoice { payee: Addr, id: String },
+    // Lets `merchant` hold up to `amount` of the sender's ledger balance until `expiry`.  Held funds cannot leave the
+    // account until the hold is captured, released or expires.
+    AuthorizeHold { merchant: Addr, amount: Uint128, expiry: Timestamp },
+    // Merchant only.  Moves up to the held amount into the merchant's balance and releases the rest.
+    CaptureHold { id: u64, amount: Uint128 },
+    // The merchant may release a hold at any time, anyone else once it has expired.
+    ReleaseHold { id: u64 },
+    // Raises `target` usei by `deadline`, to be split among `split` by weight.  With an empty split the creator gets
+    // it all.
+    CreateCampaign { target: Uint128, deadline: Timestamp, split: Vec<SplitMember> },
+    // Pledges the usei sent to an open campaign.
+    Contribute { id: u64 },
+    // After the deadline, credits the split if the target was met and otherwise opens refunds.  Anyone may call it.
+    FinalizeCampaign { id: u64 },
+    // Returns the sender's pledge to a campaign that missed its target.
+    ClaimRefund { id: u64 },
+    // Pays `amount` usei, split among `recipients` by weight, every `interval` seconds until `end`.  The usei sent is the
+    // deposit the payments and keeper bounties are taken from.  The first payment is due one interval from now.
+    CreateStandingOrder { recipients: Vec<SplitMember>, amount: Uint128, interval: u64, end: Timestamp },
+    // Payer only.  Removes the order and refunds what is left of the deposit.
+    CancelStandingOrder { id: u64 },
+    // Makes every payment that has fallen due on the given orders, crediting each order's keeper bounty to the sender.
+    // Orders that are not due are skipped.
+    ExecuteDue { ids: Vec<u64> },
+    // Owner only.  Applies to orders created afterwards.
+    UpdateKeeperBounty { bounty: Uint128 },
+}
+
+#[cw_serde]
+pub struct ShareholderMsg {
+    pub address: Addr,
+    pub shares: Uint128,
+}
+
+// Query understood by the price oracle contract.
+#[cw_serde]
+pub enum OracleQueryMsg {
+    Price { denom: String },
+}
+
+#[cw_serde]
+pub struct OraclePriceResponse {
+    // Micro-USD per unit of `denom`.
+    pub price: Decimal,
+    pub updated_at: Timestamp,
+}
+
+// The subset of an astroport-style pair's ExecuteMsg used by WithdrawAndSwap.
+#[cw_serde]
+pub enum PairExecuteMsg {
+    Swap { offer_asset: Asset, belief_price: Option<Decimal>, max_spread: Option<Decimal>, to: Option<String> },
+}
+
+#[cw_serde]
+pub struct Asset {
+    pub info: AssetInfo,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub enum AssetInfo {
+    Token { contract_addr: Addr },
+    NativeToken { denom: String },
+}
+
+#[cw_serde]
+pub struct LedgerCredit {
+    pub address: String,
+    pub amount: Uint128,
+}
+
+// Packets exchanged between sister deployments over a ledger channel, serialized as JSON.
+#[cw_serde]
+pub enum LedgerPacket {
+    // usei escrowed by the sender, credited as vouchers on the receiving side.
+    Credit { sender: String, credits: Vec<LedgerCredit> },
+    // Vouchers burned by the sender, paid out of the escrow on the receiving side.
+    Return { sender: String, credits: Vec<LedgerCredit> },
+    Balance { address: String },
+}
+
+// Acknowledgement for a LedgerPacket.  A Balance packet is answered with a serialized GetBalanceResponse.
+#[cw_serde]
+pub enum LedgerAck {
+    Result(Binary),
+    Error(String),
+}
+
+// The payer of a cheque signs the SHA-256 digest of this document, serialized as JSON.
+#[cw_serde]
+pub struct ChequeSignDoc {
+    pub chain_id: String,
+    pub contract: Addr,
+    pub cheque: Cheque,
+}
+
+// Split instructions embedded in a CW20 Send.
+#[cw_serde]
+pub enum ReceiveMsg {
+    Transfer { address1: Addr, address2: Addr },
+}
+
+// The signer of a relayed message signs the SHA-256 digest of this document, serialized as JSON.
+#[cw_serde]
+pub struct RelaySignDoc {
+    pub chain_id: String,
+    pub contract: Addr,
+    pub nonce: u64,
+    pub msg: ExecuteMsg,
+}
+
+#[cw_serde]
+pub struct MilestoneMsg {
+    pub amount: Uint128,
+    // Defaults to the payer when not set.
+    pub approver: Option<Addr>,
 }
 
 #[cw_serde]
@@ -24,6 +298,150 @@ pub enum QueryMsg {
     GetOwner {},
     #[returns(GetBalanceResponse)]
     GetBalance { address: Addr },
+    // Balance at the start of block `height`, before any of that block's transactions.
+    #[returns(GetBalanceResponse)]
+    GetBalanceAtHeight { address: Addr, height: u64 },
+    // Sum of all usei balances, the contract's total liabilities, at the start of block `height`.
+    #[returns(GetBalanceResponse)]
+    TotalBalanceAtHeight { height: u64 },
+    #[returns(EscrowResponse)]
+    Escrow { id: u64 },
+    #[returns(MilestoneResponse)]
+    Milestone { escrow_id: u64, index: u32 },
+    #[returns(HtlcResponse)]
+    Htlc { id: u64 },
+    #[returns(PubkeyResponse)]
+    Pubkey { address: Addr },
+    #[returns(ChequeNonceResponse)]
+    ChequeNonce { payer: Addr, nonce: u64 },
+    // Returns the nonce expected for the next message relayed on behalf of `address`.
+    #[returns(RelayNonceResponse)]
+    RelayNonce { address: Addr },
+    #[returns(ChannelResponse)]
+    Channel { id: u64 },
+    #[returns(ChannelsResponse)]
+    ChannelsByPayer { payer: Addr, start_after: Option<u64>, limit: Option<u32> },
+    #[returns(ChannelsResponse)]
+    ChannelsByPayee { payee: Addr, start_after: Option<u64>, limit: Option<u32> },
+    #[returns(GetBalanceResponse)]
+    GetCw20Balance { token: Addr, address: Addr },
+    #[returns(Cw20WhitelistResponse)]
+    Cw20Whitelist {},
+    #[returns(IbcChannelsResponse)]
+    IbcChannels {},
+    // Returns the in-flight ICS-20 withdrawal sent as packet `sequence` on `channel_id`.
+    #[returns(IbcTransferResponse)]
+    IbcTransfer { channel_id: String, sequence: u64 },
+    #[returns(LedgerChannelsResponse)]
+    LedgerChannels {},
+    #[returns(LedgerCounterpartiesResponse)]
+    LedgerCounterparties {},
+    // usei escrowed for credits sent over `channel_id`.
+    #[returns(GetBalanceResponse)]
+    LedgerEscrow { channel_id: String },
+    // Vouchers `address` received over `channel_id`.
+    #[returns(GetBalanceResponse)]
+    LedgerVouchers { channel_id: String, address: Addr },
+    // Last balance reported by the sister deployment on `channel_id`.
+    #[returns(RemoteBalanceResponse)]
+    RemoteBalance { channel_id: String, address: String },
+    #[returns(SwapPairResponse)]
+    SwapPair { offer_denom: String, ask_denom: String },
+    #[returns(Option<OracleConfig>)]
+    OracleConfig {},
+    #[returns(Option<StakingConfig>)]
+    StakingConfig {},
+    #[returns(StakingStateResponse)]
+    StakingState {},
+    #[returns(UnbondingWithdrawalsResponse)]
+    UnbondingWithdrawals { address: Addr },
+    #[returns(ShareholderResponse)]
+    Shareholder { address: Addr },
+    #[returns(SplitGroupResponse)]
+    SplitGroup { id: u64 },
+    #[returns(SplitGroupsResponse)]
+    SplitGroupsByAdmin { admin: Addr, start_after: Option<u64>, limit: Option<u32> },
+    // Members of a past or current version of a split group.
+    #[returns(SplitGroupVersionResponse)]
+    SplitGroupVersion { id: u64, version: u32 },
+    // Journal entries of `address`, newest first unless `order` says otherwise.
+    #[returns(AccountHistoryResponse)]
+    AccountHistory { address: Addr, start_after: Option<u64>, limit: Option<u32>, order: Option<OrderBy> },
+    #[returns(AccountSummaryResponse)]
+    AccountSummary { address: Addr },
+    #[returns(GlobalStatsResponse)]
+    GlobalStats {},
+    // Transfers made with the given client reference, oldest first.
+    #[returns(TransfersResponse)]
+    TransfersByReference { reference: String, start_after: Option<u64>, limit: Option<u32> },
+    #[returns(u64)]
+    IdempotencyWindow {},
+    #[returns(Option<IdempotencyRecord>)]
+    IdempotencyKey { sender: Addr, key: String },
+    // Receipt of the transfer with the given transfer id.
+    #[returns(TransferReceipt)]
+    Receipt { id: u64 },
+    #[returns(InvoiceResponse)]
+    Invoice { payee: Addr, id: String },
+    // `start_after` is an invoice id of `payee`.
+    #[returns(InvoicesResponse)]
+    InvoicesByPayee { payee: Addr, start_after: Option<String>, limit: Option<u32> },
+    // Invoices paid by `payer`, or addressed to it and not yet paid.  `start_after` is a (payee, id) pair.
+    #[returns(InvoicesResponse)]
+    InvoicesByPayer { payer: Addr, start_after: Option<(Addr, String)>, limit: Option<u32> },
+    #[returns(InvoicesResponse)]
+    InvoicesByStatus { status: InvoiceStatus, start_after: Option<(Addr, String)>, limit: Option<u32> },
+    #[returns(HoldResponse)]
+    Hold { id: u64 },
+    // Unexpired holds on `account`.
+    #[returns(HoldsResponse)]
+    HoldsByAccount { account: Addr, start_after: Option<u64>, limit: Option<u32> },
+    // Unexpired holds for `merchant`.
+    #[returns(HoldsResponse)]
+    HoldsByMerchant { merchant: Addr, start_after: Option<u64>, limit: Option<u32> },
+    #[returns(CampaignResponse)]
+    Campaign { id: u64 },
+    #[returns(CampaignsResponse)]
+    Campaigns { start_after: Option<u64>, limit: Option<u32> },
+    #[returns(PledgesResponse)]
+    CampaignPledges { id: u64, start_after: Option<Addr>, limit: Option<u32> },
+    // Pledges of `contributor` across campaigns.  `start_after` is a campaign id.
+    #[returns(PledgesResponse)]
+    ContributorPledges { contributor: Addr, start_after: Option<u64>, limit: Option<u32> },
+    #[returns(Uint128)]
+    KeeperBounty {},
+    #[returns(StandingOrderResponse)]
+    StandingOrder { id: u64 },
+    // Orders with a payment due now, longest overdue first.
+    #[returns(StandingOrdersResponse)]
+    DueStandingOrders { limit: Option<u32> },
+}
+
+#[cw_serde]
+pub enum OrderBy {
+    Ascending,
+    Descending,
+}
+
+impl From<OrderBy> for Order {
+    fn from(order: OrderBy) -> Order {
+        match order {
+            OrderBy::Ascending => Order::Ascending,
+            OrderBy::Descending => Order::Descending,
+        }
+    }
+}
+
+// Completion callbacks delivered by the ibc-hooks middleware for packets sent by this contract.
+#[cw_serde]
+pub enum SudoMsg {
+    IbcLifecycleComplete(IbcLifecycleComplete),
+}
+
+#[cw_serde]
+pub enum IbcLifecycleComplete {
+    IbcAck { channel: String, sequence: u64, ack: String, success: bool },
+    IbcTimeout { channel: String, sequence: u64 },
 }
 
 #[cw_serde]
@@ -48,3 +466,276 @@ pub struct GetOwnerResponse {
 pub struct GetBalanceResponse {
     pub balance: Uint128,
 }
+
+#[cw_serde]
+pub struct EscrowResponse {
+    pub id: u64,
+    pub payer: Addr,
+    pub contractor: Addr,
+    pub cancel_policy: CancelPolicy,
+    pub milestones: Vec<MilestoneResponse>,
+}
+
+#[cw_serde]
+pub struct MilestoneResponse {
+    pub index: u32,
+    pub amount: Uint128,
+    pub approver: Addr,
+    pub status: MilestoneStatus,
+}
+
+#[cw_serde]
+pub struct HtlcResponse {
+    pub id: u64,
+    pub sender: Addr,
+    pub recipient: Addr,
+    pub hash: HexBinary,
+    pub expiry: Timestamp,
+    pub amount: Coin,
+    pub status: HtlcStatus,
+    // Set once the HTLC has been claimed.
+    pub preimage: Option<HexBinary>,
+}
+
+#[cw_serde]
+pub struct PubkeyResponse {
+    pub pubkey: Option<HexBinary>,
+}
+
+#[cw_serde]
+pub struct ChequeNonceResponse {
+    pub used: bool,
+}
+
+#[cw_serde]
+pub struct RelayNonceResponse {
+    pub nonce: u64,
+}
+
+#[cw_serde]
+pub struct ChannelResponse {
+    pub id: u64,
+    pub payer: Addr,
+    pub payee: Addr,
+    pub deposit: Uint128,
+    pub claimed: Uint128,
+    pub challenge_period: u64,
+    pub status: ChannelStatus,
+}
+
+#[cw_serde]
+pub struct ChannelsResponse {
+    pub channels: Vec<ChannelResponse>,
+}
+
+#[cw_serde]
+pub struct Cw20WhitelistResponse {
+    pub tokens: Vec<Addr>,
+}
+
+#[cw_serde]
+pub struct IbcChannelsResponse {
+    pub channels: Vec<String>,
+}
+
+#[cw_serde]
+pub struct IbcTransferResponse {
+    pub sender: Addr,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct LedgerChannelsResponse {
+    pub channels: Vec<String>,
+}
+
+#[cw_serde]
+pub struct LedgerCounterpartiesResponse {
+    pub counterparties: Vec<LedgerCounterparty>,
+}
+
+#[cw_serde]
+pub struct RemoteBalanceResponse {
+    pub balance: Option<Uint128>,
+}
+
+#[cw_serde]
+pub struct SwapPairResponse {
+    pub pair: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct DelegationResponse {
+    pub validator: String,
+    pub amount: Uint128,
+}
+
+#[cw_serde]
+pub struct StakingStateResponse {
+    pub total_balance: Uint128,
+    pub delegations: Vec<DelegationResponse>,
+    pub reward_index: Decimal,
+}
+
+#[cw_serde]
+pub struct UnbondingWithdrawalsResponse {
+    pub withdrawals: Vec<UnbondingWithdrawal>,
+}
+
+#[cw_serde]
+pub struct ShareholderResponse {
+    pub shares: Uint128,
+    pub total_shares: Uint128,
+    // Fees earned and not yet claimed.
+    pub claimable: Uint128,
+}
+
+#[cw_serde]
+pub struct SplitGroupResponse {
+    pub id: u64,
+    pub name: String,
+    pub admin: Addr,
+    pub members: Vec<SplitMember>,
+    pub version: u32,
+}
+
+#[cw_serde]
+pub struct SplitGroupsResponse {
+    pub groups: Vec<SplitGroupResponse>,
+}
+
+#[cw_serde]
+pub struct SplitGroupVersionResponse {
+    pub members: Vec<SplitMember>,
+}
+
+#[cw_serde]
+pub struct JournalEntryResponse {
+    pub id: u64,
+    pub kind: EntryKind,
+    pub counterparty: Option<Addr>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+#[cw_serde]
+pub struct AccountHistoryResponse {
+    pub entries: Vec<JournalEntryResponse>,
+}
+
+#[cw_serde]
+pub struct AccountSummaryResponse {
+    pub balance: Uint128,
+    pub total_received: Uint128,
+    pub total_withdrawn: Uint128,
+    pub transfers_sent: u64,
+    pub transfers_received: u64,
+}
+
+#[cw_serde]
+pub struct GlobalStatsResponse {
+    pub transfer_count: u64,
+    pub fee_revenue: Uint128,
+    pub active_accounts: u64,
+    // Volume per denom.  CW20 volume uses the token contract address as denom.
+    pub volume: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct TransferRecordResponse {
+    pub id: u64,
+    pub sender: Addr,
+    pub recipients: Vec<Addr>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+#[cw_serde]
+pub struct TransfersResponse {
+    pub transfers: Vec<TransferRecordResponse>,
+}
+
+#[cw_serde]
+pub struct InvoiceResponse {
+    pub id: String,
+    pub payee: Addr,
+    pub payer: Option<Addr>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub expiry: Timestamp,
+    pub split: Vec<SplitMember>,
+    pub status: InvoiceStatus,
+    pub paid_by: Option<Addr>,
+}
+
+#[cw_serde]
+pub struct InvoicesResponse {
+    pub invoices: Vec<InvoiceResponse>,
+}
+
+#[cw_serde]
+pub struct HoldResponse {
+    pub id: u64,
+    pub account: Addr,
+    pub merchant: Addr,
+    pub amount: Uint128,
+    pub expiry: Timestamp,
+}
+
+#[cw_serde]
+pub struct HoldsResponse {
+    pub holds: Vec<HoldResponse>,
+}
+
+#[cw_serde]
+pub struct CampaignResponse {
+    pub id: u64,
+    pub creator: Addr,
+    pub target: Uint128,
+    pub deadline: Timestamp,
+    pub split: Vec<SplitMember>,
+    pub raised: Uint128,
+    pub status: CampaignStatus,
+}
+
+#[cw_serde]
+pub struct CampaignsResponse {
+    pub campaigns: Vec<CampaignResponse>,
+}
+
+#[cw_serde]
+pub struct PledgeResponse {
+    pub campaign_id: u64,
+    pub contributor: Addr,
+    pub amount: Uint128,
+    pub refunded: bool,
+}
+
+#[cw_serde]
+pub struct PledgesResponse {
+    pub pledges: Vec<PledgeResponse>,
+}
+
+#[cw_serde]
+pub struct StandingOrderResponse {
+    pub id: u64,
+    pub payer: Addr,
+    pub recipients: Vec<SplitMember>,
+    pub amount: Uint128,
+    pub interval: u64,
+    pub end: Timestamp,
+    pub next_payment: Timestamp,
+    pub deposit: Uint128,
+    pub bounty: Uint128,
+}
+
+#[cw_serde]
+pub struct StandingOrdersResponse {
+    pub orders: Vec<StandingOrderResponse>,
+}
@@ -1,6 +1,6 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, HexBinary, Timestamp, Uint128};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, SnapshotItem, SnapshotMap, Strategy};
 
 #[cw_serde]
 pub struct Config {
@@ -8,4 +8,660 @@ pub struct Config {
 }
 
 pub const CONFIG: Item<Config> = Item::new("config");
-pub const BALANCES: Map<Addr, Uint128> = Map::new("balances");
+// usei ledger balances.  Every change is logged, so balances can be read as of any past height.
+pub const BALANCES: SnapshotMap<Addr, Uint128> =
+    SnapshotMap::new("balances", "balances__checkpoints", "balances__changelog", Strategy::EveryBlock);
+// CW20 ledger balances, keyed by (token contract, holder).
+pub const CW20_BALANCES: Map<(Addr, Addr), Uint128> = Map::new("cw20_balances");
+// CW20 contracts accepted by the Receive hook.
+pub const CW20_WHITELIST: Map<Addr, bool> = Map::new("cw20_whitelist");
+
+#[cw_serde]
+pub enum CancelPolicy {
+    // Unreleased milestones cannot be cancelled.
+    Never {},
+    // The payer may cancel at any time.
+    Payer {},
+    // The payer may cancel once the given block height has been reached.
+    PayerAfterHeight { height: u64 },
+    // Only the contractor may cancel, handing the unreleased amounts back to the payer.
+    Contractor {},
+}
+
+#[cw_serde]
+pub enum MilestoneStatus {
+    Pending,
+    Released,
+    Cancelled,
+}
+
+#[cw_serde]
+pub struct Milestone {
+    pub amount: Uint128,
+    pub approver: Addr,
+    pub status: MilestoneStatus,
+}
+
+#[cw_serde]
+pub struct Escrow {
+    pub payer: Addr,
+    pub contractor: Addr,
+    pub cancel_policy: CancelPolicy,
+    pub milestones: Vec<Milestone>,
+}
+
+pub const ESCROW_COUNT: Item<u64> = Item::new("escrow_count");
+pub const ESCROWS: Map<u64, Escrow> = Map::new("escrows");
+
+#[cw_serde]
+pub enum HtlcStatus {
+    Locked,
+    Claimed,
+    Refunded,
+}
+
+#[cw_serde]
+pub struct Htlc {
+    pub sender: Addr,
+    pub recipient: Addr,
+    pub hash: HexBinary,
+    pub expiry: Timestamp,
+    pub amount: Coin,
+    pub status: HtlcStatus,
+    pub preimage: Option<HexBinary>,
+}
+
+pub const HTLC_COUNT: Item<u64> = Item::new("htlc_count");
+pub const HTLCS: Map<u64, Htlc> = Map::new("htlcs");
+
+// An off-chain payment promise.  The payer signs the SHA-256 digest of the JSON serialized cheque.
+#[cw_serde]
+pub struct Cheque {
+    pub payer: Addr,
+    pub payee: Addr,
+    pub amount: Uint128,
+    pub denom: String,
+    pub nonce: u64,
+    pub expiry: Timestamp,
+}
+
+pub const PUBKEYS: Map<Addr, HexBinary> = Map::new("pubkeys");
+pub const CHEQUE_NONCES: Map<(Addr, u64), bool> = Map::new("cheque_nonces");
+
+// Next expected nonce for messages relayed on behalf of each signer.
+pub const RELAY_NONCES: Map<Addr, u64> = Map::new("relay_nonces");
+
+#[cw_serde]
+pub enum ChannelStatus {
+    Open,
+    // A close has been started.  Higher vouchers are accepted until `settle_at`.
+    Closing { settle_at: Timestamp },
+    Settled,
+}
+
+#[cw_serde]
+pub struct Channel {
+    pub payer: Addr,
+    pub payee: Addr,
+    pub deposit: Uint128,
+    // Highest cumulative amount presented by the payee so far.
+    pub claimed: Uint128,
+    // Length of the challenge period in seconds.
+    pub challenge_period: u64,
+    pub status: ChannelStatus,
+    // The payer's key when the channel was opened.  Vouchers are always verified against it.
+    pub payer_pubkey: HexBinary,
+}
+
+// Longest accepted challenge period, in seconds (one year).
+pub const MAX_CHALLENGE_PERIOD: u64 = 31_536_000;
+
+// A payment channel voucher.  The payer signs the SHA-256 digest of the JSON serialized voucher.
+#[cw_serde]
+pub struct ChannelVoucher {
+    pub chain_id: String,
+    pub contract: Addr,
+    pub channel_id: u64,
+    // Cumulative amount owed to the payee.
+    pub amount: Uint128,
+}
+
+pub struct ChannelIndexes<'a> {
+    pub payer: MultiIndex<'a, Addr, Channel, u64>,
+    pub payee: MultiIndex<'a, Addr, Channel, u64>,
+}
+
+impl<'a> IndexList<Channel> for ChannelIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Channel>> + '_> {
+        let v: Vec<&dyn Index<Channel>> = vec![&self.payer, &self.payee];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn channels<'a>() -> IndexedMap<'a, u64, Channel, ChannelIndexes<'a>> {
+    let indexes = ChannelIndexes {
+        payer: MultiIndex::new(|c: &Channel| c.payer.clone(), "channels", "channels__payer"),
+        payee: MultiIndex::new(|c: &Channel| c.payee.clone(), "channels", "channels__payee"),
+    };
+    IndexedMap::new("channels", indexes)
+}
+
+pub const CHANNEL_COUNT: Item<u64> = Item::new("channel_count");
+
+// ICS-20 channels that WithdrawIbc may use.
+pub const IBC_CHANNELS: Map<String, bool> = Map::new("ibc_channels");
+
+#[cw_serde]
+pub struct IbcTransfer {
+    pub sender: Addr,
+    pub amount: Uint128,
+}
+
+// The withdrawal currently being dispatched, picked up again in `reply`.
+pub const PENDING_IBC_TRANSFER: Item<IbcTransfer> = Item::new("pending_ibc_transfer");
+// In-flight withdrawals keyed by (source channel, packet sequence), kept until the packet is acknowledged or times out.
+pub const IBC_TRANSFERS: Map<(String, u64), IbcTransfer> = Map::new("ibc_transfers");
+
+// A sister deployment, identified by the connection it is reached over and its port ("wasm.<contract address>").
+#[cw_serde]
+pub struct LedgerCounterparty {
+    pub connection_id: String,
+    pub port_id: String,
+}
+
+// Counterparties the owner trusts to open ledger channels, keyed by (connection id, port id).
+pub const LEDGER_COUNTERPARTIES: Map<(String, String), bool> = Map::new("ledger_counterparties");
+// Connected ledger channels and their counterparty.
+pub const LEDGER_CHANNELS: Map<String, LedgerCounterparty> = Map::new("ledger_channels");
+// usei sent over each ledger channel.  It stays in this contract as the backing for the credits held on the other
+// side, and is released only when those credits are sent back.
+pub const LEDGER_ESCROW: Map<String, Uint128> = Map::new("ledger_escrow");
+// Credits received from a sister deployment, keyed by (channel, account).  They are claims on the usei escrowed there,
+// so they cannot be withdrawn here and can only be returned over the same channel.
+pub const LEDGER_VOUCHERS: Map<(String, Addr), Uint128> = Map::new("ledger_vouchers");
+// Balances reported by sister deployments, keyed by (channel, remote address).
+pub const REMOTE_BALANCES: Map<(String, String), Uint128> = Map::new("remote_balances");
+
+// Pair contracts used by WithdrawAndSwap, keyed by (offer denom, ask denom).
+pub const SWAP_PAIRS: Map<(String, String), Addr> = Map::new("swap_pairs");
+
+#[cw_serde]
+pub struct PendingSwap {
+    pub recipient: Addr,
+    pub ask_denom: String,
+    pub min_out: Uint128,
+    // The contract's `ask_denom` balance before the swap, so the proceeds can be measured in `reply`.
+    pub balance_before: Uint128,
+}
+
+pub const PENDING_SWAP: Item<PendingSwap> = Item::new("pending_swap");
+
+#[cw_serde]
+pub struct OracleConfig {
+    pub oracle: Addr,
+    // Oldest oracle price, in seconds, that fiat-denominated transfers accept.
+    pub max_price_age: u64,
+}
+
+pub const ORACLE: Item<OracleConfig> = Item::new("oracle");
+
+#[cw_serde]
+pub struct StakingConfig {
+    // Validators the idle usei is delegated to, in equal parts.
+    pub validators: Vec<String>,
+    // Share of the ledger's usei kept liquid to serve withdrawals.
+    pub buffer_ratio: Decimal,
+    // Unbonding time of the chain, in seconds.
+    pub unbonding_period: u64,
+}
+
+pub const STAKING: Item<StakingConfig> = Item::new("staking");
+// usei currently delegated to each validator.
+pub const DELEGATIONS: Map<String, Uint128> = Map::new("delegations");
+// Sum of all usei ledger balances, including distributed staking rewards.  These are the contract's total
+// liabilities, logged like BALANCES.
+pub const TOTAL_BALANCE: SnapshotItem<Uint128> =
+    SnapshotItem::new("total_balance", "total_balance__checkpoints", "total_balance__changelog", Strategy::EveryBlock);
+// Staking rewards distributed per usei held since staking was enabled.
+pub const REWARD_INDEX: SnapshotItem<Decimal> =
+    SnapshotItem::new("reward_index", "reward_index__checkpoints", "reward_index__changelog", Strategy::EveryBlock);
+// REWARD_INDEX as of each account's last balance change.
+pub const REWARD_SNAPSHOTS: SnapshotMap<Addr, Decimal> =
+    SnapshotMap::new("reward_snapshots", "reward_snapshots__checkpoints", "reward_snapshots__changelog", Strategy::EveryBlock);
+
+#[cw_serde]
+pub struct UnbondingWithdrawal {
+    pub amount: Uint128,
+    pub release_at: Timestamp,
+}
+
+// Withdrawals that exceeded the liquid buffer and wait for the undelegated usei.
+pub const UNBONDING_WITHDRAWALS: Map<Addr, Vec<UnbondingWithdrawal>> = Map::new("unbonding_withdrawals");
+// The contract's usei balance before rewards were claimed, so they can be measured in `reply`.
+pub const PENDING_REWARD_CLAIM: Item<Uint128> = Item::new("pending_reward_claim");
+// A withdrawal that unbonds, waiting in `reply` for the rewards of the validators it touches to be distributed.
+pub const PENDING_WITHDRAWAL: Item<(Addr, Uint128)> = Item::new("pending_withdrawal");
+
+#[cw_serde]
+pub struct Shareholder {
+    pub shares: Uint128,
+    // FEE_PER_SHARE when this shareholder's fees were last settled.
+    pub fee_per_share_snapshot: Decimal,
+    // Fees settled but not yet claimed.
+    pub unclaimed: Uint128,
+}
+
+// Holders of the fee revenue collected by TransferWithTip.
+pub const SHAREHOLDERS: Map<Addr, Shareholder> = Map::new("shareholders");
+pub const TOTAL_SHARES: Item<Uint128> = Item::new("total_shares");
+// Fees collected per share since the first shareholder was registered.
+pub const FEE_PER_SHARE: Item<Decimal> = Item::new("fee_per_share");
+
+// cw4 groups whose membership is recorded through their MemberChangedHook, with the height recording started at.
+pub const TRACKED_GROUPS: Map<Addr, u64> = Map::new("tracked_groups");
+// Recorded weight of each (group, member).  Removed members are kept with weight 0, so their history stays readable.
+pub const GROUP_WEIGHTS: SnapshotMap<(Addr, Addr), u64> =
+    SnapshotMap::new("group_weights", "group_weights__checkpoints", "group_weights__changelog", Strategy::EveryBlock);
+
+#[cw_serde]
+pub struct SplitMember {
+    pub address: Addr,
+    pub weight: u64,
+}
+
+// A recipient list kept on-chain and paid with TransferToSplitGroup.
+#[cw_serde]
+pub struct SplitGroup {
+    pub name: String,
+    pub admin: Addr,
+    pub members: Vec<SplitMember>,
+    // Bumped whenever the members change.  Every version's members are kept in SPLIT_GROUP_VERSIONS.
+    pub version: u32,
+}
+
+pub struct SplitGroupIndexes<'a> {
+    pub admin: MultiIndex<'a, Addr, SplitGroup, u64>,
+}
+
+impl<'a> IndexList<SplitGroup> for SplitGroupIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<SplitGroup>> + '_> {
+        let v: Vec<&dyn Index<SplitGroup>> = vec![&self.admin];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn split_groups<'a>() -> IndexedMap<'a, u64, SplitGroup, SplitGroupIndexes<'a>> {
+    let indexes = SplitGroupIndexes {
+        admin: MultiIndex::new(|g: &SplitGroup| g.admin.clone(), "split_groups", "split_groups__admin"),
+    };
+    IndexedMap::new("split_groups", indexes)
+}
+
+pub const SPLIT_GROUP_COUNT: Item<u64> = Item::new("split_group_count");
+// Members of every version of every split group, keyed by (group id, version).
+pub const SPLIT_GROUP_VERSIONS: Map<(u64, u32), Vec<SplitMember>> = Map::new("split_group_versions");
+
+#[cw_serde]
+pub enum EntryKind {
+    // Credited by `counterparty`.
+    TransferIn,
+    // Debited from the ledger balance and credited to `counterparty`.
+    TransferOut,
+    // The flat tip paid alongside a transfer.  `counterparty` is the owner when no fee shareholders are registered.
+    Tip,
+    // A tip credited to the owner while no fee shareholders are registered.
+    TipReceived,
+    Withdraw,
+    // An IBC withdrawal credited back because it failed, which no longer counts as withdrawn.
+    WithdrawReverted,
+    FeeClaim,
+    // Paid outside a transfer: a released milestone, a claimed HTLC or a settled channel.  `counterparty` is the payer.
+    Payment,
+    // Returned to the payer: a cancelled escrow, the rest of a settled channel or ledger credits the other chain refused.
+    Refund,
+    // Debited into the escrow of a ledger channel and sent to the other chain as credits.
+    LedgerSend,
+    // Paid out of the escrow of a ledger channel for credits the other chain returned.
+    LedgerReturn,
+    // Staking rewards picked up since the previous balance change.
+    Reward,
+}
+
+// One line of an account's statement.  Entries are only ever appended.
+#[cw_serde]
+pub struct JournalEntry {
+    pub account: Addr,
+    pub kind: EntryKind,
+    pub counterparty: Option<Addr>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+pub struct JournalIndexes<'a> {
+    pub account: MultiIndex<'a, Addr, JournalEntry, u64>,
+}
+
+impl<'a> IndexList<JournalEntry> for JournalIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<JournalEntry>> + '_> {
+        let v: Vec<&dyn Index<JournalEntry>> = vec![&self.account];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn journal<'a>() -> IndexedMap<'a, u64, JournalEntry, JournalIndexes<'a>> {
+    let indexes = JournalIndexes {
+        account: MultiIndex::new(|e: &JournalEntry| e.account.clone(), "journal", "journal__account"),
+    };
+    IndexedMap::new("journal", indexes)
+}
+
+pub const JOURNAL_COUNT: Item<u64> = Item::new("journal_count");
+
+// Lifetime usei counters of an account.
+#[cw_serde]
+#[derive(Default)]
+pub struct AccountStats {
+    pub total_received: Uint128,
+    pub total_withdrawn: Uint128,
+    pub transfers_sent: u64,
+    pub transfers_received: u64,
+}
+
+pub const ACCOUNT_STATS: Map<Addr, AccountStats> = Map::new("account_stats");
+
+#[cw_serde]
+#[derive(Default)]
+pub struct GlobalStats {
+    pub transfer_count: u64,
+    // Tips collected, in usei.
+    pub fee_revenue: Uint128,
+    // Accounts that have sent or received at least one transfer.
+    pub active_accounts: u64,
+}
+
+pub const GLOBAL_STATS: Item<GlobalStats> = Item::new("global_stats");
+// Transferred volume keyed by denom.  CW20 volume is keyed by the token contract address.
+pub const VOLUME: Map<String, Uint128> = Map::new("volume");
+
+// Longest accepted memo and client reference, in bytes.
+pub const MAX_MEMO_LENGTH: usize = 256;
+pub const MAX_REFERENCE_LENGTH: usize = 64;
+
+// Flat tip, in usei, paid on top of TransferWithTip and TransferUsdWithTip.
+pub const TRANSFER_TIP: u128 = 10;
+
+// The memo and client reference a sender attached to a transfer.
+#[cw_serde]
+#[derive(Default)]
+pub struct TransferNote {
+    pub memo: Option<String>,
+    pub reference: Option<String>,
+}
+
+// One transfer as a whole.  The per-recipient amounts are in the recipients' journals.
+#[cw_serde]
+pub struct TransferRecord {
+    pub sender: Addr,
+    pub recipients: Vec<Addr>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub note: TransferNote,
+    pub height: u64,
+    pub time: Timestamp,
+}
+
+pub struct TransferIndexes<'a> {
+    // Transfers without a reference are indexed under the empty string, which is never a valid reference.
+    pub reference: MultiIndex<'a, String, TransferRecord, u64>,
+}
+
+impl<'a> IndexList<TransferRecord> for TransferIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<TransferRecord>> + '_> {
+        let v: Vec<&dyn Index<TransferRecord>> = vec![&self.reference];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn transfers<'a>() -> IndexedMap<'a, u64, TransferRecord, TransferIndexes<'a>> {
+    let indexes = TransferIndexes {
+        reference: MultiIndex::new(
+            |t: &TransferRecord| t.note.reference.clone().unwrap_or_default(),
+            "transfers",
+            "transfers__reference",
+        ),
+    };
+    IndexedMap::new("transfers", indexes)
+}
+
+pub const TRANSFER_COUNT: Item<u64> = Item::new("transfer_count");
+
+// Blocks an idempotency key is remembered for until the owner sets a window.
+pub const DEFAULT_IDEMPOTENCY_WINDOW: u64 = 14_400;
+pub const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 64;
+
+pub const IDEMPOTENCY_WINDOW: Item<u64> = Item::new("idempotency_window");
+
+// The transfer made with an idempotency key.  `params` is the SHA-256 digest of the request parameters.
+#[cw_serde]
+pub struct IdempotencyRecord {
+    pub params: Binary,
+    pub transfer_id: u64,
+    pub expires_at: u64,
+}
+
+pub const IDEMPOTENCY_KEYS: Map<(Addr, String), IdempotencyRecord> = Map::new("idempotency_keys");
+// The same keys ordered by expiry height, so expired ones can be pruned.
+pub const IDEMPOTENCY_EXPIRIES: Map<(u64, Addr, String), bool> = Map::new("idempotency_expiries");
+
+// Credited to one recipient of a transfer, with the recipient's balance afterwards.
+#[cw_serde]
+pub struct ReceiptCredit {
+    pub address: Addr,
+    pub amount: Uint128,
+    pub balance: Uint128,
+}
+
+// The outcome of a transfer.  It is returned as response data and kept for the Receipt query.
+#[cw_serde]
+pub struct TransferReceipt {
+    pub transfer_id: u64,
+    pub denom: String,
+    pub credits: Vec<ReceiptCredit>,
+    // Tip, or standing order keeper bounty, paid on top of the transfer.
+    pub fee: Uint128,
+    // Funds sent beyond what the transfer needed, returned to the sender.
+    pub refund: Uint128,
+    // The sender's ledger balance afterwards, for transfers paid out of it.
+    pub sender_balance: Option<Uint128>,
+    // The split group version paid, so the weights used can be looked up with QueryMsg::SplitGroupVersion.
+    pub split_group: Option<SplitGroupRef>,
+}
+
+#[cw_serde]
+pub struct SplitGroupRef {
+    pub id: u64,
+    pub version: u32,
+}
+
+pub const RECEIPTS: Map<u64, TransferReceipt> = Map::new("receipts");
+
+#[cw_serde]
+pub enum InvoiceStatus {
+    Open,
+    Paid,
+    Cancelled,
+    Expired,
+}
+
+// A payment request from `payee`.  When `split` is empty the whole amount goes to the payee.
+#[cw_serde]
+pub struct Invoice {
+    pub payee: Addr,
+    // Only this account may pay, if set.
+    pub payer: Option<Addr>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub expiry: Timestamp,
+    pub split: Vec<SplitMember>,
+    pub status: InvoiceStatus,
+    pub paid_by: Option<Addr>,
+}
+
+pub struct InvoiceIndexes<'a> {
+    pub payee: MultiIndex<'a, Addr, Invoice, (Addr, String)>,
+    // The account that paid, or else the designated payer.  Open invoices anyone may pay are under the empty string.
+    pub payer: MultiIndex<'a, String, Invoice, (Addr, String)>,
+    pub status: MultiIndex<'a, u8, Invoice, (Addr, String)>,
+}
+
+impl<'a> IndexList<Invoice> for InvoiceIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Invoice>> + '_> {
+        let v: Vec<&dyn Index<Invoice>> = vec![&self.payee, &self.payer, &self.status];
+        Box::new(v.into_iter())
+    }
+}
+
+// Invoices keyed by (payee, id), so each payee picks its own ids.
+pub fn invoices<'a>() -> IndexedMap<'a, (Addr, String), Invoice, InvoiceIndexes<'a>> {
+    let indexes = InvoiceIndexes {
+        payee: MultiIndex::new(|i: &Invoice| i.payee.clone(), "invoices", "invoices__payee"),
+        payer: MultiIndex::new(
+            |i: &Invoice| i.paid_by.as_ref().or(i.payer.as_ref()).map(Addr::to_string).unwrap_or_default(),
+            "invoices",
+            "invoices__payer",
+        ),
+        status: MultiIndex::new(|i: &Invoice| i.status.clone() as u8, "invoices", "invoices__status"),
+    };
+    IndexedMap::new("invoices", indexes)
+}
+
+// Reserves up to `amount` of the account's ledger balance for `merchant` to capture until `expiry`.  Holds are
+// removed once captured or released.
+#[cw_serde]
+pub struct Hold {
+    pub account: Addr,
+    pub merchant: Addr,
+    pub amount: Uint128,
+    pub expiry: Timestamp,
+}
+
+pub struct HoldIndexes<'a> {
+    pub account: MultiIndex<'a, Addr, Hold, u64>,
+    pub merchant: MultiIndex<'a, Addr, Hold, u64>,
+}
+
+impl<'a> IndexList<Hold> for HoldIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Hold>> + '_> {
+        let v: Vec<&dyn Index<Hold>> = vec![&self.account, &self.merchant];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn holds<'a>() -> IndexedMap<'a, u64, Hold, HoldIndexes<'a>> {
+    let indexes = HoldIndexes {
+        account: MultiIndex::new(|h: &Hold| h.account.clone(), "holds", "holds__account"),
+        merchant: MultiIndex::new(|h: &Hold| h.merchant.clone(), "holds", "holds__merchant"),
+    };
+    IndexedMap::new("holds", indexes)
+}
+
+pub const HOLD_COUNT: Item<u64> = Item::new("hold_count");
+
+#[cw_serde]
+pub enum CampaignStatus {
+    Open,
+    // The target was met and the funds were credited to the split.
+    Funded,
+    // The deadline passed short of the target.  Contributors can claim refunds.
+    Failed,
+}
+
+// A crowdfunding campaign.  When `split` is empty the creator gets all the funds.
+#[cw_serde]
+pub struct Campaign {
+    pub creator: Addr,
+    pub target: Uint128,
+    pub deadline: Timestamp,
+    pub split: Vec<SplitMember>,
+    pub raised: Uint128,
+    pub status: CampaignStatus,
+}
+
+pub const CAMPAIGNS: Map<u64, Campaign> = Map::new("campaigns");
+pub const CAMPAIGN_COUNT: Item<u64> = Item::new("campaign_count");
+
+#[cw_serde]
+pub struct Pledge {
+    pub contributor: Addr,
+    pub amount: Uint128,
+    pub refunded: bool,
+}
+
+pub struct PledgeIndexes<'a> {
+    pub contributor: MultiIndex<'a, Addr, Pledge, (u64, Addr)>,
+}
+
+impl<'a> IndexList<Pledge> for PledgeIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Pledge>> + '_> {
+        let v: Vec<&dyn Index<Pledge>> = vec![&self.contributor];
+        Box::new(v.into_iter())
+    }
+}
+
+// Pledges keyed by (campaign id, contributor).
+pub fn pledges<'a>() -> IndexedMap<'a, (u64, Addr), Pledge, PledgeIndexes<'a>> {
+    let indexes = PledgeIndexes {
+        contributor: MultiIndex::new(|p: &Pledge| p.contributor.clone(), "pledges", "pledges__contributor"),
+    };
+    IndexedMap::new("pledges", indexes)
+}
+
+// Paid from each standing order's deposit to whoever executes a payment, until the owner sets a bounty.
+pub const DEFAULT_KEEPER_BOUNTY: u128 = 10;
+
+pub const KEEPER_BOUNTY: Item<Uint128> = Item::new("keeper_bounty");
+
+// Longest accepted standing order interval, in seconds (ten years).
+pub const MAX_STANDING_ORDER_INTERVAL: u64 = 315_360_000;
+// Most periods of one standing order paid by a single ExecuteDue.  An order further behind catches up over later calls.
+pub const MAX_PERIODS_PER_EXECUTION: u32 = 12;
+
+// A prepaid recurring payment.  It is removed, and what is left of the deposit refunded, once it runs past `end` or
+// the deposit cannot cover another period.
+#[cw_serde]
+pub struct StandingOrder {
+    pub payer: Addr,
+    pub recipients: Vec<SplitMember>,
+    // Paid every `interval` seconds.
+    pub amount: Uint128,
+    pub interval: u64,
+    pub end: Timestamp,
+    pub next_payment: Timestamp,
+    // What is left of the prepaid usei.
+    pub deposit: Uint128,
+    // The keeper bounty when the order was created, paid for each period whatever the owner sets later.
+    pub bounty: Uint128,
+}
+
+pub struct StandingOrderIndexes<'a> {
+    // Seconds of the next payment time.
+    pub next_payment: MultiIndex<'a, u64, StandingOrder, u64>,
+}
+
+impl<'a> IndexList<StandingOrder> for StandingOrderIndexes<'a> {
+    fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<StandingOrder>> + '_> {
+        let v: Vec<&dyn Index<StandingOrder>> = vec![&self.next_payment];
+        Box::new(v.into_iter())
+    }
+}
+
+pub fn standing_orders<'a>() -> IndexedMap<'a, u64, StandingOrder, StandingOrderIndexes<'a>> {
+    let indexes = StandingOrderIndexes {
+        next_payment: MultiIndex::new(|o: &StandingOrder| o.next_payment.seconds(), "standing_orders", "standing_orders__next_payment"),
+    };
+    IndexedMap::new("standing_orders", indexes)
+}
+
+pub const STANDING_ORDER_COUNT: Item<u64> = Item::new("standing_order_count");
@@ -1,12 +1,29 @@
 #[cfg(test)]
 mod test_module {
-    use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info};
-    use cosmwasm_std::{Addr, coins, from_binary, Deps, DepsMut, Uint128};
+    use cosmwasm_std::testing::{
+        mock_dependencies, mock_env, mock_ibc_channel_connect_ack, mock_ibc_channel_open_try, mock_ibc_packet_ack, mock_ibc_packet_recv,
+        mock_ibc_packet_timeout, mock_info
+    };
+    use cosmwasm_std::{
+        Addr, attr, coins, from_json, to_json_binary, BankMsg, CosmosMsg, Deps, DepsMut, Event, HexBinary, IbcAcknowledgement, IbcMsg, IbcOrder, IbcTimeout, Reply, SubMsgResponse,
+        SubMsgResult, Uint128, WasmMsg
+    };
+    use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg};
+    use bech32::{ToBase32, Variant};
+    use cosmwasm_std::to_json_vec;
+    use k256::ecdsa::signature::hazmat::PrehashSigner;
+    use k256::ecdsa::{Signature, SigningKey};
+    use sha2::{Digest, Sha256};
 
-    use crate::contract::{execute, instantiate, query};
+    use crate::contract::{execute, instantiate, migrate, pubkey_to_address, query, reply, sudo};
     use crate::error::ContractError;
-    use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, GetOwnerResponse, GetBalanceResponse};
-    use crate::state::Config;
+    use crate::ibc::{ibc_channel_connect, ibc_channel_open, ibc_packet_ack, ibc_packet_receive, ibc_packet_timeout, IBC_APP_VERSION};
+    use crate::msg::{
+        ChannelsResponse, ChequeSignDoc, EscrowResponse, ExecuteMsg, HtlcResponse, IbcLifecycleComplete, IbcTransferResponse, InstantiateMsg, LedgerAck, MigrateMsg, LedgerChannelsResponse, LedgerCredit, LedgerPacket, MilestoneMsg, MilestoneResponse, QueryMsg, ReceiveMsg, RelayNonceResponse, RelaySignDoc, RemoteBalanceResponse, SudoMsg, GetOwnerResponse,
+        GetBalanceResponse, ShareholderMsg, ShareholderResponse, SplitGroupsResponse, SplitGroupVersionResponse,
+        AccountHistoryResponse, AccountSummaryResponse, GlobalStatsResponse, OrderBy, TransfersResponse, InvoiceResponse, InvoicesResponse, HoldsResponse, CampaignsResponse, PledgeResponse, PledgesResponse, StandingOrdersResponse
+    };
+    use crate::state::{CampaignStatus, CancelPolicy, ChannelStatus, ChannelVoucher, Cheque, Config, EntryKind, HtlcStatus, IdempotencyRecord, InvoiceStatus, LedgerCounterparty, MilestoneStatus, ReceiptCredit, SplitGroupRef, SplitMember, TransferReceipt};
 
     fn mock_init(deps: DepsMut) {
         let msg = InstantiateMsg {};
@@ -17,7 +34,7 @@ mod test_module {
 
     fn assert_config_state(deps: Deps, expected: Config) {
         let res = query(deps, mock_env(), QueryMsg::Config {}).unwrap();
-        let value: Config = from_binary(&res).unwrap();
+        let value: Config = from_json(&res).unwrap();
         assert_eq!(value, expected);
     }
 
@@ -26,12 +43,12 @@ mod test_module {
             deps,
             mock_env(),
             QueryMsg::GetBalance {
-                address: address
+                address
             },
         )
         .unwrap();
 
-        let value: GetBalanceResponse = from_binary(&res).unwrap();
+        let value: GetBalanceResponse = from_json(&res).unwrap();
         assert_eq!(expected_balance, value.balance);
     }
 
@@ -58,7 +75,7 @@ mod test_module {
 
         // Querying for the owner of the contract results in address "creator", as defined in mock_init.
         let res = query(deps.as_ref(), mock_env(), QueryMsg::GetOwner{}).unwrap();
-        let value: GetOwnerResponse = from_binary(&res).unwrap();
+        let value: GetOwnerResponse = from_json(&res).unwrap();
         assert_eq!("creator", value.address)
     }
 
@@ -76,6 +93,9 @@ mod test_module {
             address1: bob_addr.clone(),
             address2: carl_addr.clone(),
             amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
         };
 
         let _res = execute(deps.as_mut(), mock_env(), info_alice, transfer_msg)
@@ -98,6 +118,9 @@ mod test_module {
             address1: bob_addr.clone(),
             address2: carl_addr.clone(),
             amount: Uint128::from(5u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
         };
 
         let _res = execute(deps.as_mut(), mock_env(), info_alice, transfer_msg)
@@ -129,6 +152,9 @@ mod test_module {
             address1: bob_addr.clone(),
             address2: carl_addr.clone(),
             amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
         };
 
         let _transfer_res = execute(deps.as_mut(), mock_env(), info_alice, transfer_msg)
@@ -160,6 +186,9 @@ mod test_module {
             address1: bob_addr.clone(),
             address2: carl_addr.clone(),
             amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
         };
 
         let res = execute(deps.as_mut(), mock_env(), info_alice, transfer_msg);
@@ -183,6 +212,9 @@ mod test_module {
             address1: bob_addr.clone(),
             address2: carl_addr.clone(),
             amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
         };
 
         let _res = execute(deps.as_mut(), mock_env(), info_alice, transfer_msg);
@@ -215,6 +247,9 @@ mod test_module {
             address1: bob_addr.clone(),
             address2: carl_addr.clone(),
             amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
         };
 
         let _res = execute(deps.as_mut(), mock_env(), info_alice, transfer_msg)
@@ -224,4 +259,2327 @@ mod test_module {
         // Verify that the creator got a 10 usei tip.
         assert_balance(deps.as_ref(), Addr::unchecked("creator"), Uint128::from(10u32));
     }
+
+    fn set_shares(deps: DepsMut, shares: &[(&str, u32)]) {
+        let shareholders = shares.iter()
+            .map(|(address, shares)| ShareholderMsg { address: Addr::unchecked(*address), shares: Uint128::from(*shares) })
+            .collect();
+        execute(deps, mock_env(), mock_info("creator", &[]), ExecuteMsg::UpdateShareholders { shareholders })
+            .expect("Owner updates the shareholders");
+    }
+
+    fn tip(deps: DepsMut) {
+        let transfer_msg = ExecuteMsg::TransferWithTip {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(100u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps, mock_env(), mock_info("Alice", &coins(110, "usei")), transfer_msg).expect("Alice transfers with a tip");
+    }
+
+    fn assert_claimable(deps: Deps, address: &str, expected: u32) {
+        let res = query(deps, mock_env(), QueryMsg::Shareholder { address: Addr::unchecked(address) }).unwrap();
+        let value: ShareholderResponse = from_json(&res).unwrap();
+        assert_eq!(Uint128::from(expected), value.claimable);
+    }
+
+    #[test]
+    fn tips_shared_among_shareholders() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        set_shares(deps.as_mut(), &[("dave", 1), ("erin", 4)]);
+        tip(deps.as_mut());
+        assert_claimable(deps.as_ref(), "dave", 2);
+        assert_claimable(deps.as_ref(), "erin", 8);
+        assert_balance(deps.as_ref(), Addr::unchecked("creator"), Uint128::zero());
+
+        // Fees earned at one share are kept, the next tip is split 6:4.
+        set_shares(deps.as_mut(), &[("dave", 6)]);
+        tip(deps.as_mut());
+        assert_claimable(deps.as_ref(), "dave", 8);
+        assert_claimable(deps.as_ref(), "erin", 12);
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("erin", &[]), ExecuteMsg::ClaimFees {}).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send { to_address: "erin".to_string(), amount: coins(12, "usei") })
+        );
+        assert_claimable(deps.as_ref(), "erin", 0);
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("erin", &[]), ExecuteMsg::ClaimFees {});
+        match res {
+            Err(ContractError::NoFeesToClaim {}) => (),
+            _ => panic!("Must return no fees error"),
+        }
+    }
+
+    fn account_history(deps: Deps, address: &str, start_after: Option<u64>, limit: Option<u32>, order: Option<OrderBy>) -> Vec<(u64, EntryKind)> {
+        let msg = QueryMsg::AccountHistory { address: Addr::unchecked(address), start_after, limit, order };
+        let value: AccountHistoryResponse = from_json(query(deps, mock_env(), msg).unwrap()).unwrap();
+        value.entries.into_iter().map(|e| (e.id, e.kind)).collect()
+    }
+
+    // Replays the journal of `address` and checks that it adds up to its ledger balance in `denom`.
+    fn assert_journal_reconciles(deps: Deps, address: &str, denom: &str, expected_balance: Uint128) {
+        let msg = QueryMsg::AccountHistory { address: Addr::unchecked(address), start_after: None, limit: Some(30), order: Some(OrderBy::Ascending) };
+        let value: AccountHistoryResponse = from_json(query(deps, mock_env(), msg).unwrap()).unwrap();
+        let balance = value.entries.iter().filter(|e| e.denom == denom).fold(0i128, |acc, e| {
+            let amount = e.amount.u128() as i128;
+            match e.kind {
+                EntryKind::TransferOut | EntryKind::Withdraw | EntryKind::LedgerSend => acc - amount,
+                EntryKind::Tip | EntryKind::FeeClaim => acc,
+                _ => acc + amount,
+            }
+        });
+        assert_eq!(expected_balance.u128() as i128, balance);
+    }
+    #[test]
+    fn account_history_pages_through_journal() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1000, "usei")), transfer_msg).unwrap();
+        let transfer_msg = ExecuteMsg::TransferBalance { recipient: Addr::unchecked("Carl"), amount: Uint128::from(100u32), memo: None, reference: None, idempotency_key: None };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), transfer_msg).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::Withdraw { amount: Uint128::from(100u32) }).unwrap();
+
+        // Newest first by default.
+        assert_eq!(
+            vec![(5, EntryKind::Withdraw), (3, EntryKind::TransferOut)],
+            account_history(deps.as_ref(), "Bob", None, Some(2), None)
+        );
+        assert_eq!(vec![(1, EntryKind::TransferIn)], account_history(deps.as_ref(), "Bob", Some(3), Some(2), None));
+        assert_eq!(
+            vec![(2, EntryKind::TransferIn), (4, EntryKind::TransferIn)],
+            account_history(deps.as_ref(), "Carl", None, None, Some(OrderBy::Ascending))
+        );
+
+        let msg = QueryMsg::AccountHistory { address: Addr::unchecked("Carl"), start_after: None, limit: Some(1), order: None };
+        let value: AccountHistoryResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(Some(Addr::unchecked("Bob")), value.entries[0].counterparty);
+        assert_eq!(Uint128::from(100u32), value.entries[0].amount);
+        assert_eq!(mock_env().block.height, value.entries[0].height);
+    }
+
+    #[test]
+    fn account_and_global_stats() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let transfer_msg = ExecuteMsg::TransferWithTip {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1010, "usei")), transfer_msg).unwrap();
+        let transfer_msg = ExecuteMsg::TransferBalance { recipient: Addr::unchecked("Carl"), amount: Uint128::from(100u32), memo: None, reference: None, idempotency_key: None };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), transfer_msg).unwrap();
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::Withdraw { amount: Uint128::from(100u32) }).unwrap();
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::AccountSummary { address: Addr::unchecked("Bob") }).unwrap();
+        let value: AccountSummaryResponse = from_json(res).unwrap();
+        assert_eq!(
+            AccountSummaryResponse {
+                balance: Uint128::from(300u32),
+                total_received: Uint128::from(500u32),
+                total_withdrawn: Uint128::from(100u32),
+                transfers_sent: 1,
+                transfers_received: 1,
+            },
+            value
+        );
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::GlobalStats {}).unwrap();
+        let value: GlobalStatsResponse = from_json(res).unwrap();
+        assert_eq!(
+            GlobalStatsResponse {
+                transfer_count: 2,
+                fee_revenue: Uint128::from(10u32),
+                active_accounts: 3,
+                volume: coins(1100, "usei"),
+            },
+            value
+        );
+    }
+
+    #[test]
+    fn transfers_searchable_by_reference() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: Some("March rent".to_string()),
+            reference: Some("INV-1".to_string()),
+            idempotency_key: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1000, "usei")), transfer_msg).unwrap();
+        assert_eq!(vec![attr("transfer_id", "1"), attr("memo", "March rent"), attr("reference", "INV-1")], res.attributes);
+
+        let transfer_msg = ExecuteMsg::TransferBalance {
+            recipient: Addr::unchecked("Carl"),
+            amount: Uint128::from(100u32),
+            memo: None,
+            reference: Some("INV-2".to_string()),
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), transfer_msg).unwrap();
+        let transfer_msg = ExecuteMsg::TransferBalance {
+            recipient: Addr::unchecked("Alice"),
+            amount: Uint128::from(50u32),
+            memo: None,
+            reference: Some("INV-1".to_string()),
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("Carl", &[]), transfer_msg).unwrap();
+
+        let msg = QueryMsg::TransfersByReference { reference: "INV-1".to_string(), start_after: None, limit: None };
+        let value: TransfersResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![1, 3], value.transfers.iter().map(|t| t.id).collect::<Vec<_>>());
+        assert_eq!(vec![Addr::unchecked("Bob"), Addr::unchecked("Carl")], value.transfers[0].recipients);
+        assert_eq!(Some("March rent".to_string()), value.transfers[0].memo);
+        assert_eq!(Addr::unchecked("Carl"), value.transfers[1].sender);
+
+        let msg = QueryMsg::TransfersByReference { reference: "INV-1".to_string(), start_after: Some(1), limit: None };
+        let value: TransfersResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(1, value.transfers.len());
+        assert_eq!(Uint128::from(50u32), value.transfers[0].amount);
+
+        let transfer_msg = ExecuteMsg::TransferBalance {
+            recipient: Addr::unchecked("Carl"),
+            amount: Uint128::from(1u32),
+            memo: Some("x".repeat(257)),
+            reference: None,
+            idempotency_key: None,
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), transfer_msg) {
+            Err(ContractError::MemoTooLong { max: 256 }) => (),
+            _ => panic!("Must return memo too long error"),
+        }
+        let transfer_msg = ExecuteMsg::TransferBalance {
+            recipient: Addr::unchecked("Carl"),
+            amount: Uint128::from(1u32),
+            memo: None,
+            reference: Some(String::new()),
+            idempotency_key: None,
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), transfer_msg) {
+            Err(ContractError::InvalidReference { max: 64 }) => (),
+            _ => panic!("Must return invalid reference error"),
+        }
+    }
+
+    fn keyed_transfer(amount: u32, key: &str) -> ExecuteMsg {
+        ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(amount),
+            memo: None,
+            reference: None,
+            idempotency_key: Some(key.to_string()),
+        }
+    }
+
+    #[test]
+    fn idempotent_transfer_retries() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        let window = ExecuteMsg::UpdateIdempotencyWindow { blocks: 5 };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), window).unwrap();
+
+        let info = mock_info("Alice", &coins(1000, "usei"));
+        let res = execute(deps.as_mut(), env_at(100), info.clone(), keyed_transfer(1000, "payout-1")).unwrap();
+        assert_eq!(vec![attr("transfer_id", "1")], res.attributes);
+        let receipt = res.data;
+
+        // A retry pays nothing, refunds what was sent and returns the original receipt.
+        let res = execute(deps.as_mut(), env_at(104), info.clone(), keyed_transfer(1000, "payout-1")).unwrap();
+        assert_eq!(vec![attr("transfer_id", "1"), attr("idempotent_replay", "true")], res.attributes);
+        assert_eq!(receipt, res.data);
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send { to_address: "Alice".to_string(), amount: coins(1000, "usei") })
+        );
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+
+        match execute(deps.as_mut(), env_at(104), info.clone(), keyed_transfer(800, "payout-1")) {
+            Err(ContractError::IdempotencyKeyReused { key }) => assert_eq!("payout-1", key),
+            _ => panic!("Must return idempotency key reused error"),
+        }
+
+        // Keys are scoped to the sender.
+        let res = execute(deps.as_mut(), env_at(104), mock_info("Dave", &coins(1000, "usei")), keyed_transfer(1000, "payout-1")).unwrap();
+        assert_eq!(vec![attr("transfer_id", "2")], res.attributes);
+
+        let msg = QueryMsg::IdempotencyKey { sender: Addr::unchecked("Alice"), key: "payout-1".to_string() };
+        let record: Option<IdempotencyRecord> = from_json(query(deps.as_ref(), env_at(104), msg.clone()).unwrap()).unwrap();
+        assert_eq!(Some(1), record.map(|r| r.transfer_id));
+        let record: Option<IdempotencyRecord> = from_json(query(deps.as_ref(), env_at(105), msg).unwrap()).unwrap();
+        assert_eq!(None, record);
+
+        // Once expired, the key pays again.
+        let res = execute(deps.as_mut(), env_at(105), info, keyed_transfer(1000, "payout-1")).unwrap();
+        assert_eq!(vec![attr("transfer_id", "3")], res.attributes);
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(1500u32));
+    }
+
+    #[test]
+    fn idempotent_tip_and_balance_transfer_retries() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let tip_msg = ExecuteMsg::TransferWithTip {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: Some("payout-1".to_string()),
+        };
+        let info = mock_info("Alice", &coins(1010, "usei"));
+        execute(deps.as_mut(), mock_env(), info.clone(), tip_msg.clone()).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), info, tip_msg).unwrap();
+        assert!(res.attributes.contains(&attr("idempotent_replay", "true")));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("creator"), Uint128::from(10u32));
+
+        // A retried balance transfer is not debited twice.
+        let balance_msg = ExecuteMsg::TransferBalance {
+            recipient: Addr::unchecked("Carl"),
+            amount: Uint128::from(100u32),
+            memo: None,
+            reference: None,
+            idempotency_key: Some("rent".to_string()),
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), balance_msg.clone()).unwrap();
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), balance_msg).unwrap();
+        assert!(res.attributes.contains(&attr("idempotent_replay", "true")));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(400u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Carl"), Uint128::from(600u32));
+
+        // A key used by one kind of transfer cannot be replayed by another.
+        match execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1000, "usei")), keyed_transfer(1000, "payout-1")) {
+            Err(ContractError::IdempotencyKeyReused { .. }) => (),
+            _ => panic!("Must return idempotency key reused error"),
+        }
+    }
+
+    #[test]
+    fn transfers_return_receipts() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let transfer_msg = ExecuteMsg::TransferWithTip {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1010, "usei")), transfer_msg).unwrap();
+        let receipt: TransferReceipt = from_json(res.data.unwrap()).unwrap();
+        assert_eq!(
+            TransferReceipt {
+                transfer_id: 1,
+                denom: "usei".to_string(),
+                credits: vec![
+                    ReceiptCredit { address: Addr::unchecked("Bob"), amount: Uint128::from(500u32), balance: Uint128::from(500u32) },
+                    ReceiptCredit { address: Addr::unchecked("Carl"), amount: Uint128::from(500u32), balance: Uint128::from(500u32) },
+                ],
+                fee: Uint128::from(10u32),
+                refund: Uint128::zero(),
+                sender_balance: None,
+                split_group: None,
+            },
+            receipt
+        );
+
+        let transfer_msg = ExecuteMsg::TransferBalance { recipient: Addr::unchecked("Carl"), amount: Uint128::from(100u32), memo: None, reference: None, idempotency_key: None };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), transfer_msg).unwrap();
+        let receipt: TransferReceipt = from_json(res.data.unwrap()).unwrap();
+        assert_eq!(
+            vec![ReceiptCredit { address: Addr::unchecked("Carl"), amount: Uint128::from(100u32), balance: Uint128::from(600u32) }],
+            receipt.credits
+        );
+        assert_eq!(Some(Uint128::from(400u32)), receipt.sender_balance);
+
+        let stored: TransferReceipt = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Receipt { id: 2 }).unwrap()).unwrap();
+        assert_eq!(receipt, stored);
+        assert!(query(deps.as_ref(), mock_env(), QueryMsg::Receipt { id: 3 }).is_err());
+    }
+
+    fn env_at(height: u64) -> cosmwasm_std::Env {
+        let mut env = mock_env();
+        env.block.height = height;
+        env
+    }
+
+    fn balance_at(deps: Deps, address: &str, height: u64) -> Uint128 {
+        let msg = QueryMsg::GetBalanceAtHeight { address: Addr::unchecked(address), height };
+        let value: GetBalanceResponse = from_json(query(deps, mock_env(), msg).unwrap()).unwrap();
+        value.balance
+    }
+
+    fn total_at(deps: Deps, height: u64) -> Uint128 {
+        let value: GetBalanceResponse = from_json(query(deps, mock_env(), QueryMsg::TotalBalanceAtHeight { height }).unwrap()).unwrap();
+        value.balance
+    }
+
+    #[test]
+    fn balances_at_past_heights() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), env_at(100), mock_info("Alice", &coins(1000, "usei")), transfer_msg).unwrap();
+        execute(deps.as_mut(), env_at(200), mock_info("Bob", &[]), ExecuteMsg::Withdraw { amount: Uint128::from(300u32) }).unwrap();
+
+        // A height's own transactions are not included.
+        assert_eq!(Uint128::zero(), balance_at(deps.as_ref(), "Bob", 100));
+        assert_eq!(Uint128::from(500u32), balance_at(deps.as_ref(), "Bob", 101));
+        assert_eq!(Uint128::from(500u32), balance_at(deps.as_ref(), "Bob", 200));
+        assert_eq!(Uint128::from(200u32), balance_at(deps.as_ref(), "Bob", 201));
+        assert_eq!(Uint128::from(1000u32), total_at(deps.as_ref(), 150));
+        assert_eq!(Uint128::from(700u32), total_at(deps.as_ref(), 201));
+    }
+
+    #[test]
+    fn migrate_seeds_total_balance() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        // Balances as stored before they were snapshotted.
+        let legacy_balances: cw_storage_plus::Map<Addr, Uint128> = cw_storage_plus::Map::new("balances");
+        legacy_balances.save(deps.as_mut().storage, Addr::unchecked("Bob"), &Uint128::from(500u32)).unwrap();
+        legacy_balances.save(deps.as_mut().storage, Addr::unchecked("Carl"), &Uint128::from(300u32)).unwrap();
+
+        migrate(deps.as_mut(), env_at(100), MigrateMsg {}).expect("contract migrates");
+        execute(deps.as_mut(), env_at(200), mock_info("Bob", &[]), ExecuteMsg::Withdraw { amount: Uint128::from(500u32) }).unwrap();
+
+        assert_eq!(Uint128::from(800u32), total_at(deps.as_ref(), 150));
+        assert_eq!(Uint128::from(500u32), balance_at(deps.as_ref(), "Bob", 150));
+        assert_eq!(Uint128::from(300u32), total_at(deps.as_ref(), 201));
+    }
+
+    fn create_escrow(deps: DepsMut, cancel_policy: CancelPolicy) -> u64 {
+        // Alice pays Bob in two tranches, the second of which is approved by Dave.
+        let info_alice = mock_info("Alice", &coins(300, "usei"));
+        let create_msg = ExecuteMsg::CreateEscrow {
+            contractor: Addr::unchecked("Bob"),
+            milestones: vec![
+                MilestoneMsg { amount: Uint128::from(100u32), approver: None },
+                MilestoneMsg { amount: Uint128::from(200u32), approver: Some(Addr::unchecked("Dave")) },
+            ],
+            cancel_policy,
+        };
+        let res = execute(deps, mock_env(), info_alice, create_msg)
+            .expect("Alice successfully creates an escrow");
+        res.attributes.iter().find(|a| a.key == "escrow_id").unwrap().value.parse().unwrap()
+    }
+
+    #[test]
+    fn escrow_release_milestones_in_order() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        let id = create_escrow(deps.as_mut(), CancelPolicy::Payer {});
+        let bob_addr = Addr::unchecked("Bob");
+
+        // Dave only approves the second milestone, so he cannot release the first.
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Dave", &[]), ExecuteMsg::ReleaseMilestone { id });
+        match res {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::ReleaseMilestone { id })
+            .expect("Alice releases the first milestone");
+        assert_balance(deps.as_ref(), bob_addr.clone(), Uint128::from(100u32));
+
+        execute(deps.as_mut(), mock_env(), mock_info("Dave", &[]), ExecuteMsg::ReleaseMilestone { id })
+            .expect("Dave releases the second milestone");
+        assert_balance(deps.as_ref(), bob_addr, Uint128::from(300u32));
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Escrow { id }).unwrap();
+        let value: EscrowResponse = from_json(&res).unwrap();
+        assert!(value.milestones.iter().all(|m| m.status == MilestoneStatus::Released));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::ReleaseMilestone { id });
+        match res {
+            Err(ContractError::NoPendingMilestones {}) => (),
+            _ => panic!("Must return no pending milestones error"),
+        }
+    }
+
+    #[test]
+    fn escrow_insufficient_funds() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let create_msg = ExecuteMsg::CreateEscrow {
+            contractor: Addr::unchecked("Bob"),
+            milestones: vec![MilestoneMsg { amount: Uint128::from(100u32), approver: None }],
+            cancel_policy: CancelPolicy::Payer {},
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(99, "usei")), create_msg);
+        match res {
+            Err(ContractError::InsufficientFundsSend {}) => (),
+            _ => panic!("Must return insufficient funds error"),
+        }
+
+        // Overpaying would strand the excess in the contract, so only the exact total is accepted.
+        let create_msg = ExecuteMsg::CreateEscrow {
+            contractor: Addr::unchecked("Bob"),
+            milestones: vec![MilestoneMsg { amount: Uint128::from(100u32), approver: None }],
+            cancel_policy: CancelPolicy::Payer {},
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(101, "usei")), create_msg);
+        match res {
+            Err(ContractError::InvalidFunds {}) => (),
+            _ => panic!("Must return invalid funds error"),
+        }
+    }
+
+    #[test]
+    fn escrow_cancel_refunds_unreleased_milestones() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        let id = create_escrow(deps.as_mut(), CancelPolicy::PayerAfterHeight { height: mock_env().block.height + 10 });
+
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::ReleaseMilestone { id })
+            .expect("Alice releases the first milestone");
+
+        // Cancelling before the configured height is not allowed.
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::CancelEscrow { id });
+        match res {
+            Err(ContractError::CancelNotAllowed {}) => (),
+            _ => panic!("Must return cancel not allowed error"),
+        }
+
+        let mut env = mock_env();
+        env.block.height += 10;
+        execute(deps.as_mut(), env, mock_info("Alice", &[]), ExecuteMsg::CancelEscrow { id })
+            .expect("Alice cancels the escrow");
+        assert_balance(deps.as_ref(), Addr::unchecked("Alice"), Uint128::from(200u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(100u32));
+        assert_eq!(vec![(2, EntryKind::Refund)], account_history(deps.as_ref(), "Alice", None, None, None));
+        assert_eq!(vec![(1, EntryKind::Payment)], account_history(deps.as_ref(), "Bob", None, None, None));
+        assert_journal_reconciles(deps.as_ref(), "Alice", "usei", Uint128::from(200u32));
+        assert_journal_reconciles(deps.as_ref(), "Bob", "usei", Uint128::from(100u32));
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Milestone { escrow_id: id, index: 1 }).unwrap();
+        let value: MilestoneResponse = from_json(&res).unwrap();
+        assert_eq!(MilestoneStatus::Cancelled, value.status);
+    }
+
+    fn lock_htlc(deps: DepsMut, preimage: &[u8], funds: &[cosmwasm_std::Coin]) -> u64 {
+        let lock_msg = ExecuteMsg::LockHtlc {
+            recipient: Addr::unchecked("Bob"),
+            hash: HexBinary::from(Sha256::digest(preimage).to_vec()),
+            expiry: mock_env().block.time.plus_seconds(3600),
+        };
+        let res = execute(deps, mock_env(), mock_info("Alice", funds), lock_msg)
+            .expect("Alice successfully locks an HTLC");
+        res.attributes.iter().find(|a| a.key == "htlc_id").unwrap().value.parse().unwrap()
+    }
+
+    #[test]
+    fn htlc_claim_with_preimage() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        let id = lock_htlc(deps.as_mut(), b"secret", &coins(100, "usei"));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Relayer", &[]), ExecuteMsg::ClaimHtlc { id, preimage: HexBinary::from(b"wrong") });
+        match res {
+            Err(ContractError::InvalidPreimage {}) => (),
+            _ => panic!("Must return invalid preimage error"),
+        }
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Relayer", &[]), ExecuteMsg::ClaimHtlc { id, preimage: HexBinary::from(b"secret") })
+            .expect("Relayer claims the HTLC on Bob's behalf");
+        assert!(res.attributes.iter().any(|a| a.key == "preimage" && a.value == HexBinary::from(b"secret").to_hex()));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(100u32));
+
+        // The revealed preimage is queryable by the counterparty.
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Htlc { id }).unwrap();
+        let value: HtlcResponse = from_json(&res).unwrap();
+        assert_eq!(HtlcStatus::Claimed, value.status);
+        assert_eq!(Some(HexBinary::from(b"secret")), value.preimage);
+    }
+
+    #[test]
+    fn htlc_refund_after_expiry() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        let id = lock_htlc(deps.as_mut(), b"secret", &coins(100, "uatom"));
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::RefundHtlc { id });
+        match res {
+            Err(ContractError::HtlcNotExpired {}) => (),
+            _ => panic!("Must return not expired error"),
+        }
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(3600);
+        let res = execute(deps.as_mut(), env.clone(), mock_info("Alice", &[]), ExecuteMsg::RefundHtlc { id })
+            .expect("Alice is refunded after expiry");
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send { to_address: "Alice".to_string(), amount: coins(100, "uatom") })
+        );
+
+        // An expired HTLC can no longer be claimed.
+        let res = execute(deps.as_mut(), env, mock_info("Bob", &[]), ExecuteMsg::ClaimHtlc { id, preimage: HexBinary::from(b"secret") });
+        match res {
+            Err(ContractError::HtlcNotLocked {}) => (),
+            _ => panic!("Must return not locked error"),
+        }
+    }
+
+    fn alice_key() -> SigningKey {
+        SigningKey::from_bytes(&[7u8; 32].into()).unwrap()
+    }
+
+    fn sign_cheque(key: &SigningKey, cheque: &Cheque) -> HexBinary {
+        sign_cheque_for(key, cheque, mock_env().contract.address)
+    }
+
+    fn sign_cheque_for(key: &SigningKey, cheque: &Cheque, contract: Addr) -> HexBinary {
+        let sign_doc = ChequeSignDoc {
+            chain_id: mock_env().block.chain_id,
+            contract,
+            cheque: cheque.clone(),
+        };
+        let hash = Sha256::digest(to_json_vec(&sign_doc).unwrap());
+        let signature: Signature = key.sign_prehash(&hash).unwrap();
+        HexBinary::from(signature.to_bytes().to_vec())
+    }
+
+    fn fund_alice_and_register_key(mut deps: DepsMut) {
+        // Alice receives 500 usei through a transfer, then registers her key.
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Alice"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps.branch(), mock_env(), mock_info("Dave", &coins(1000, "usei")), transfer_msg).unwrap();
+
+        let pubkey = HexBinary::from(alice_key().verifying_key().to_encoded_point(true).as_bytes());
+        execute(deps, mock_env(), mock_info("Alice", &[]), ExecuteMsg::RegisterPubkey { pubkey })
+            .expect("Alice registers her public key");
+    }
+
+    #[test]
+    fn redeem_signed_cheque() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_alice_and_register_key(deps.as_mut());
+
+        let cheque = Cheque {
+            payer: Addr::unchecked("Alice"),
+            payee: Addr::unchecked("Bob"),
+            amount: Uint128::from(200u32),
+            denom: "usei".to_string(),
+            nonce: 1,
+            expiry: mock_env().block.time.plus_seconds(60),
+        };
+        let signature = sign_cheque(&alice_key(), &cheque);
+
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::RedeemCheque { cheque: cheque.clone(), signature: signature.clone() })
+            .expect("Bob redeems Alice's cheque");
+        assert_balance(deps.as_ref(), Addr::unchecked("Alice"), Uint128::from(300u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(200u32));
+
+        // Replaying the same cheque fails.
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::RedeemCheque { cheque, signature });
+        match res {
+            Err(ContractError::NonceAlreadyUsed {}) => (),
+            _ => panic!("Must return nonce already used error"),
+        }
+    }
+
+    #[test]
+    fn redeem_tampered_or_overdrawn_cheque() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_alice_and_register_key(deps.as_mut());
+
+        let mut cheque = Cheque {
+            payer: Addr::unchecked("Alice"),
+            payee: Addr::unchecked("Bob"),
+            amount: Uint128::from(200u32),
+            denom: "usei".to_string(),
+            nonce: 1,
+            expiry: mock_env().block.time.plus_seconds(60),
+        };
+        let signature = sign_cheque(&alice_key(), &cheque);
+
+        // Bob raises the amount after Alice signed the cheque.
+        cheque.amount = Uint128::from(400u32);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::RedeemCheque { cheque: cheque.clone(), signature });
+        match res {
+            Err(ContractError::InvalidSignature {}) => (),
+            _ => panic!("Must return invalid signature error"),
+        }
+
+        // A correctly signed cheque larger than Alice's balance is rejected.
+        cheque.amount = Uint128::from(600u32);
+        let signature = sign_cheque(&alice_key(), &cheque);
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::RedeemCheque { cheque, signature });
+        match res {
+            Err(ContractError::InsufficientBalance {}) => (),
+            _ => panic!("Must return insufficient balance error"),
+        }
+        assert_balance(deps.as_ref(), Addr::unchecked("Alice"), Uint128::from(500u32));
+    }
+
+    #[test]
+    fn redeem_cheque_signed_for_another_contract() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_alice_and_register_key(deps.as_mut());
+
+        let cheque = Cheque {
+            payer: Addr::unchecked("Alice"),
+            payee: Addr::unchecked("Bob"),
+            amount: Uint128::from(200u32),
+            denom: "usei".to_string(),
+            nonce: 1,
+            expiry: mock_env().block.time.plus_seconds(60),
+        };
+        // Alice signed the cheque for a different deployment, so it cannot be redeemed here.
+        let signature = sign_cheque_for(&alice_key(), &cheque, Addr::unchecked("other_ledger"));
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::RedeemCheque { cheque, signature });
+        match res {
+            Err(ContractError::InvalidSignature {}) => (),
+            _ => panic!("Must return invalid signature error"),
+        }
+        assert_balance(deps.as_ref(), Addr::unchecked("Alice"), Uint128::from(500u32));
+    }
+
+    fn sign_relay(key: &SigningKey, msg: ExecuteMsg, nonce: u64) -> ExecuteMsg {
+        let env = mock_env();
+        let sign_doc = RelaySignDoc {
+            chain_id: env.block.chain_id,
+            contract: env.contract.address,
+            nonce,
+            msg: msg.clone(),
+        };
+        let hash = Sha256::digest(to_json_vec(&sign_doc).unwrap());
+        let signature: Signature = key.sign_prehash(&hash).unwrap();
+        ExecuteMsg::Relay {
+            msg: Box::new(msg),
+            signer_pubkey: HexBinary::from(key.verifying_key().to_encoded_point(true).as_bytes()),
+            signature: HexBinary::from(signature.to_bytes().to_vec()),
+            nonce,
+        }
+    }
+
+    #[test]
+    fn relay_transfer_balance_for_signer() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let key = alice_key();
+        let signer = pubkey_to_address(key.verifying_key().to_encoded_point(true).as_bytes()).unwrap();
+        assert!(signer.as_str().starts_with("sei1"));
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: signer.clone(),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("Dave", &coins(1000, "usei")), transfer_msg).unwrap();
+
+        let inner = ExecuteMsg::TransferBalance { recipient: Addr::unchecked("Bob"), amount: Uint128::from(150u32), memo: None, reference: None, idempotency_key: None };
+        let relay_msg = sign_relay(&key, inner, 0);
+        execute(deps.as_mut(), mock_env(), mock_info("Relayer", &[]), relay_msg.clone())
+            .expect("Relayer submits the signed transfer");
+        assert_balance(deps.as_ref(), signer.clone(), Uint128::from(350u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(150u32));
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::RelayNonce { address: signer }).unwrap();
+        let value: RelayNonceResponse = from_json(&res).unwrap();
+        assert_eq!(1, value.nonce);
+
+        // The same signed message cannot be replayed.
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Relayer", &[]), relay_msg);
+        match res {
+            Err(ContractError::InvalidRelayNonce { expected: 1 }) => (),
+            _ => panic!("Must return invalid relay nonce error"),
+        }
+    }
+
+    #[test]
+    fn relay_rejects_funded_and_forged_messages() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        // Transfer requires funds to be sent, so it cannot be relayed.
+        let inner = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(10u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Relayer", &[]), sign_relay(&alice_key(), inner, 0));
+        match res {
+            Err(ContractError::RelayNotAllowed {}) => (),
+            _ => panic!("Must return relay not allowed error"),
+        }
+
+        // An IBC withdrawal pays out to an arbitrary remote address, so it is not relayable either.
+        let inner = ExecuteMsg::WithdrawIbc {
+            channel_id: "channel-0".to_string(),
+            remote_address: "osmo1mallory".to_string(),
+            amount: Uint128::from(10u32),
+            timeout: 600,
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Relayer", &[]), sign_relay(&alice_key(), inner, 0));
+        match res {
+            Err(ContractError::RelayNotAllowed {}) => (),
+            _ => panic!("Must return relay not allowed error"),
+        }
+
+        // Swapping in a different inner message invalidates the signature.
+        let inner = ExecuteMsg::Withdraw { amount: Uint128::from(10u32) };
+        let forged = match sign_relay(&alice_key(), inner, 0) {
+            ExecuteMsg::Relay { signer_pubkey, signature, nonce, .. } => ExecuteMsg::Relay {
+                msg: Box::new(ExecuteMsg::Withdraw { amount: Uint128::from(20u32) }),
+                signer_pubkey,
+                signature,
+                nonce,
+            },
+            _ => unreachable!(),
+        };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Relayer", &[]), forged);
+        match res {
+            Err(ContractError::InvalidSignature {}) => (),
+            _ => panic!("Must return invalid signature error"),
+        }
+    }
+
+    fn sign_voucher(key: &SigningKey, channel_id: u64, amount: u32) -> HexBinary {
+        let env = mock_env();
+        let voucher = ChannelVoucher {
+            chain_id: env.block.chain_id,
+            contract: env.contract.address,
+            channel_id,
+            amount: Uint128::from(amount),
+        };
+        let hash = Sha256::digest(to_json_vec(&voucher).unwrap());
+        let signature: Signature = key.sign_prehash(&hash).unwrap();
+        HexBinary::from(signature.to_bytes().to_vec())
+    }
+
+    fn open_channel(deps: DepsMut) -> u64 {
+        let open_msg = ExecuteMsg::OpenChannel { payee: Addr::unchecked("Bob"), challenge_period: 100 };
+        let res = execute(deps, mock_env(), mock_info("Alice", &coins(1000, "usei")), open_msg)
+            .expect("Alice opens a channel to Bob");
+        res.attributes.iter().find(|a| a.key == "channel_id").unwrap().value.parse().unwrap()
+    }
+
+    #[test]
+    fn channel_close_with_challenge() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_alice_and_register_key(deps.as_mut());
+        let id = open_channel(deps.as_mut());
+
+        let close_msg = ExecuteMsg::CloseChannel { id, amount: Uint128::from(300u32), signature: sign_voucher(&alice_key(), id, 300) };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), close_msg).expect("Bob closes with a voucher for 300");
+
+        // Bob finds a later voucher during the challenge period.
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(50);
+        let challenge_msg = ExecuteMsg::CloseChannel { id, amount: Uint128::from(500u32), signature: sign_voucher(&alice_key(), id, 500) };
+        execute(deps.as_mut(), env.clone(), mock_info("Bob", &[]), challenge_msg).expect("Bob presents a voucher for 500");
+
+        let res = execute(deps.as_mut(), env.clone(), mock_info("Alice", &[]), ExecuteMsg::SettleChannel { id });
+        match res {
+            Err(ContractError::ChallengePeriodActive {}) => (),
+            _ => panic!("Must return challenge period active error"),
+        }
+
+        env.block.time = env.block.time.plus_seconds(50);
+        execute(deps.as_mut(), env, mock_info("Alice", &[]), ExecuteMsg::SettleChannel { id }).expect("Channel settles");
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+        // Alice already held 500 usei in the ledger before opening the channel.
+        assert_balance(deps.as_ref(), Addr::unchecked("Alice"), Uint128::from(1000u32));
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::ChannelsByPayee { payee: Addr::unchecked("Bob"), start_after: None, limit: None }).unwrap();
+        let value: ChannelsResponse = from_json(&res).unwrap();
+        assert_eq!(1, value.channels.len());
+        assert_eq!(ChannelStatus::Settled, value.channels[0].status);
+    }
+
+    #[test]
+    fn channel_payer_reclaims_after_timeout() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_alice_and_register_key(deps.as_mut());
+        let id = open_channel(deps.as_mut());
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::RequestChannelClose { id });
+        match res {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::RequestChannelClose { id })
+            .expect("Alice starts the timeout");
+
+        // Once the challenge period is over, Bob can no longer present a voucher.
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        let close_msg = ExecuteMsg::CloseChannel { id, amount: Uint128::from(300u32), signature: sign_voucher(&alice_key(), id, 300) };
+        let res = execute(deps.as_mut(), env.clone(), mock_info("Bob", &[]), close_msg);
+        match res {
+            Err(ContractError::ChallengePeriodOver {}) => (),
+            _ => panic!("Must return challenge period over error"),
+        }
+
+        execute(deps.as_mut(), env, mock_info("Alice", &[]), ExecuteMsg::SettleChannel { id }).expect("Channel settles");
+        assert_balance(deps.as_ref(), Addr::unchecked("Alice"), Uint128::from(1500u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::zero());
+    }
+
+    #[test]
+    fn channel_vouchers_survive_key_rotation() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_alice_and_register_key(deps.as_mut());
+        let id = open_channel(deps.as_mut());
+
+        // Alice rotates her key and asks to close, hoping to void the vouchers Bob holds.
+        let new_key = SigningKey::from_bytes(&[9u8; 32].into()).unwrap();
+        let pubkey = HexBinary::from(new_key.verifying_key().to_encoded_point(true).as_bytes());
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::RegisterPubkey { pubkey })
+            .expect("Alice registers a new key");
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::RequestChannelClose { id })
+            .expect("Alice starts the timeout");
+
+        // Vouchers signed with the key the channel was opened with remain valid.
+        let close_msg = ExecuteMsg::CloseChannel { id, amount: Uint128::from(300u32), signature: sign_voucher(&alice_key(), id, 300) };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), close_msg).expect("Bob presents a voucher for 300");
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(100);
+        execute(deps.as_mut(), env, mock_info("Alice", &[]), ExecuteMsg::SettleChannel { id }).expect("Channel settles");
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(300u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Alice"), Uint128::from(1200u32));
+    }
+
+    #[test]
+    fn channel_rejects_unbounded_challenge_period() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_alice_and_register_key(deps.as_mut());
+
+        let open_msg = ExecuteMsg::OpenChannel { payee: Addr::unchecked("Bob"), challenge_period: u64::MAX };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1000, "usei")), open_msg);
+        match res {
+            Err(ContractError::InvalidChallengePeriod { .. }) => (),
+            _ => panic!("Must return invalid challenge period error"),
+        }
+    }
+
+    fn receive_cw20(deps: DepsMut, token: &str, amount: u32) -> Result<cosmwasm_std::Response, ContractError> {
+        let split = ReceiveMsg::Transfer { address1: Addr::unchecked("Bob"), address2: Addr::unchecked("Carl") };
+        let receive_msg = ExecuteMsg::Receive(Cw20ReceiveMsg {
+            sender: "Alice".to_string(),
+            amount: Uint128::from(amount),
+            msg: to_json_binary(&split).unwrap(),
+        });
+        execute(deps, mock_env(), mock_info(token, &[]), receive_msg)
+    }
+
+    fn assert_cw20_balance(deps: Deps, token: &str, address: &str, expected_balance: Uint128) {
+        let res = query(deps, mock_env(), QueryMsg::GetCw20Balance { token: Addr::unchecked(token), address: Addr::unchecked(address) }).unwrap();
+        let value: GetBalanceResponse = from_json(&res).unwrap();
+        assert_eq!(expected_balance, value.balance);
+    }
+
+    #[test]
+    fn cw20_receive_and_withdraw() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let whitelist_msg = ExecuteMsg::UpdateCw20Whitelist { add: vec![Addr::unchecked("token")], remove: vec![] };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), whitelist_msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), whitelist_msg).expect("Owner whitelists the token");
+
+        receive_cw20(deps.as_mut(), "token", 1000).expect("Alice sends 1000 tokens");
+        assert_cw20_balance(deps.as_ref(), "token", "Bob", Uint128::from(500u32));
+        assert_cw20_balance(deps.as_ref(), "token", "Carl", Uint128::from(500u32));
+        // The native ledger is untouched.
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::zero());
+
+        let withdraw_msg = ExecuteMsg::WithdrawCw20 { token: Addr::unchecked("token"), amount: Uint128::from(400u32) };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), withdraw_msg).expect("Bob withdraws 400 tokens");
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Wasm(WasmMsg::Execute {
+                contract_addr: "token".to_string(),
+                msg: to_json_binary(&Cw20ExecuteMsg::Transfer { recipient: "Bob".to_string(), amount: Uint128::from(400u32) }).unwrap(),
+                funds: vec![],
+            })
+        );
+        assert_cw20_balance(deps.as_ref(), "token", "Bob", Uint128::from(100u32));
+        assert_eq!(
+            vec![(1, EntryKind::TransferIn), (3, EntryKind::Withdraw)],
+            account_history(deps.as_ref(), "Bob", None, None, Some(OrderBy::Ascending))
+        );
+        assert_journal_reconciles(deps.as_ref(), "Bob", "token", Uint128::from(100u32));
+        assert_journal_reconciles(deps.as_ref(), "Bob", "usei", Uint128::zero());
+    }
+
+    #[test]
+    fn cw20_receive_from_unlisted_token() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let res = receive_cw20(deps.as_mut(), "scam", 1000);
+        match res {
+            Err(ContractError::Cw20NotWhitelisted { .. }) => (),
+            _ => panic!("Must return not whitelisted error"),
+        }
+    }
+
+    fn fund_bob_and_allow_channel(mut deps: DepsMut) {
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps.branch(), mock_env(), mock_info("Alice", &coins(1000, "usei")), transfer_msg).unwrap();
+
+        let channels_msg = ExecuteMsg::UpdateIbcChannels { add: vec!["channel-0".to_string()], remove: vec![] };
+        execute(deps, mock_env(), mock_info("creator", &[]), channels_msg).expect("Owner allows channel-0");
+    }
+
+    fn withdraw_ibc_msg(channel_id: &str) -> ExecuteMsg {
+        ExecuteMsg::WithdrawIbc {
+            channel_id: channel_id.to_string(),
+            remote_address: "osmo1bob".to_string(),
+            amount: Uint128::from(200u32),
+            timeout: 600,
+        }
+    }
+
+    #[test]
+    fn withdraw_ibc_refunded_on_timeout() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_bob_and_allow_channel(deps.as_mut());
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), withdraw_ibc_msg("channel-1"));
+        match res {
+            Err(ContractError::IbcChannelNotAllowed { .. }) => (),
+            _ => panic!("Must return channel not allowed error"),
+        }
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), withdraw_ibc_msg("channel-0"))
+            .expect("Bob withdraws 200 usei over IBC");
+        // The transfer goes out as a raw MsgTransfer whose memo registers the ibc-hooks callback.
+        match &res.messages[0].msg {
+            CosmosMsg::Stargate { type_url, value } => {
+                assert_eq!("/ibc.applications.transfer.v1.MsgTransfer", type_url);
+                let memo = br#"{"ibc_callback":"cosmos2contract"}"#;
+                assert!(value.windows(memo.len()).any(|w| w == memo));
+                assert!(value.windows(8).any(|w| w == b"osmo1bob"));
+            }
+            msg => panic!("Unexpected message {:?}", msg),
+        }
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(300u32));
+
+        // The transfer module reports the packet it sent.
+        let sent = Reply {
+            id: res.messages[0].id,
+            result: SubMsgResult::Ok(SubMsgResponse {
+                events: vec![Event::new("send_packet")
+                    .add_attribute("packet_src_channel", "channel-0")
+                    .add_attribute("packet_sequence", "7")],
+                data: None,
+            }),
+        };
+        reply(deps.as_mut(), mock_env(), sent).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::IbcTransfer { channel_id: "channel-0".to_string(), sequence: 7 }).unwrap();
+        let value: IbcTransferResponse = from_json(&res).unwrap();
+        assert_eq!(Uint128::from(200u32), value.amount);
+
+        let timeout = SudoMsg::IbcLifecycleComplete(IbcLifecycleComplete::IbcTimeout { channel: "channel-0".to_string(), sequence: 7 });
+        sudo(deps.as_mut(), mock_env(), timeout).unwrap();
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+
+        // The refund reverses the withdrawal in Bob's journal and lifetime counters.
+        assert_eq!(
+            vec![(1, EntryKind::TransferIn), (3, EntryKind::Withdraw), (4, EntryKind::WithdrawReverted)],
+            account_history(deps.as_ref(), "Bob", None, None, Some(OrderBy::Ascending))
+        );
+        assert_journal_reconciles(deps.as_ref(), "Bob", "usei", Uint128::from(500u32));
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::AccountSummary { address: Addr::unchecked("Bob") }).unwrap();
+        let value: AccountSummaryResponse = from_json(res).unwrap();
+        assert_eq!(Uint128::zero(), value.total_withdrawn);
+    }
+
+    #[test]
+    fn withdraw_ibc_refunded_on_dispatch_error() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        fund_bob_and_allow_channel(deps.as_mut());
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), withdraw_ibc_msg("channel-0")).unwrap();
+        let failed = Reply { id: res.messages[0].id, result: SubMsgResult::Err("channel closed".to_string()) };
+        reply(deps.as_mut(), mock_env(), failed).unwrap();
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+    }
+
+    // Mirrors the ibc-hooks derivation: sha256(sha256("ibc-wasm-hook-intermediary") || "channel/sender").
+    fn hook_sender(channel: &str, original_sender: &str) -> Addr {
+        let mut preimage = Sha256::digest(b"ibc-wasm-hook-intermediary").to_vec();
+        preimage.extend_from_slice(format!("{}/{}", channel, original_sender).as_bytes());
+        let hash = Sha256::digest(&preimage);
+        Addr::unchecked(bech32::encode("sei", hash.to_base32(), Variant::Bech32).unwrap())
+    }
+
+    fn ibc_hook_transfer_msg(original_sender: &str) -> ExecuteMsg {
+        ExecuteMsg::IbcHookTransfer {
+            channel: "channel-0".to_string(),
+            original_sender: original_sender.to_string(),
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            memo: None,
+            reference: Some("INV-7".to_string()),
+        }
+    }
+
+    #[test]
+    fn ibc_hook_transfer_from_intermediate_sender() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        // usei sent back from Osmosis unwinds its "transfer/channel-0/usei" trace and arrives as native usei.
+        let sender = hook_sender("channel-0", "osmo1alice").to_string();
+        let res = execute(deps.as_mut(), mock_env(), mock_info(&sender, &coins(1000, "usei")), ibc_hook_transfer_msg("osmo1alice"))
+            .expect("Hook transfer is split");
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Carl"), Uint128::from(500u32));
+
+        // It is recorded like any other transfer, sent by the intermediate account.
+        assert!(res.attributes.contains(&attr("reference", "INV-7")));
+        let receipt: TransferReceipt = from_json(res.data.unwrap()).unwrap();
+        let stored: TransferReceipt = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Receipt { id: receipt.transfer_id }).unwrap()).unwrap();
+        assert_eq!(receipt, stored);
+        let msg = QueryMsg::AccountHistory { address: Addr::unchecked("Bob"), start_after: None, limit: None, order: None };
+        let value: AccountHistoryResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![(EntryKind::TransferIn, Some(Addr::unchecked(&sender)))], value.entries.into_iter().map(|e| (e.kind, e.counterparty)).collect::<Vec<_>>());
+
+        // A memo claiming a different original sender does not match the derived account.
+        let res = execute(deps.as_mut(), mock_env(), mock_info(&sender, &coins(1000, "usei")), ibc_hook_transfer_msg("osmo1mallory"));
+        match res {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+    }
+
+    #[test]
+    fn ibc_hook_transfer_of_foreign_denom() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        // uosmo arrives as a voucher denom derived from its trace.
+        let voucher = format!("ibc/{}", HexBinary::from(Sha256::digest(b"transfer/channel-0/uosmo").to_vec()).to_hex().to_uppercase());
+        let sender = hook_sender("channel-0", "osmo1alice").to_string();
+        let res = execute(deps.as_mut(), mock_env(), mock_info(&sender, &coins(1000, &voucher)), ibc_hook_transfer_msg("osmo1alice"));
+        match res {
+            Err(ContractError::InsufficientFundsSend {}) => (),
+            _ => panic!("Must return insufficient funds error"),
+        }
+    }
+
+    fn split_members(weights: &[(&str, u64)]) -> Vec<SplitMember> {
+        weights.iter().map(|(address, weight)| SplitMember { address: Addr::unchecked(*address), weight: *weight }).collect()
+    }
+
+    #[test]
+    fn split_group_versions_and_payments() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let create_msg = ExecuteMsg::CreateSplitGroup { name: "team".to_string(), members: split_members(&[("dave", 1), ("erin", 3)]) };
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), create_msg).expect("Alice creates a split group");
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &coins(100, "usei")), ExecuteMsg::TransferToSplitGroup { id: 1, memo: None, reference: None }).unwrap();
+        assert!(res.attributes.contains(&attr("version", "1")));
+        let receipt: TransferReceipt = from_json(res.data.unwrap()).unwrap();
+        let first_payment = receipt.transfer_id;
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(25u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("erin"), Uint128::from(75u32));
+
+        let update_msg = ExecuteMsg::UpdateSplitGroup { id: 1, members: split_members(&[("dave", 1), ("erin", 1)]) };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), update_msg.clone());
+        match res {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), update_msg).expect("Alice updates the split group");
+
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &coins(10, "usei")), ExecuteMsg::TransferToSplitGroup { id: 1, memo: None, reference: None }).unwrap();
+        assert!(res.attributes.contains(&attr("version", "2")));
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(30u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("erin"), Uint128::from(80u32));
+
+        // The weights of the first payment can still be audited from the version its receipt records.
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::Receipt { id: first_payment }).unwrap();
+        let receipt: TransferReceipt = from_json(res).unwrap();
+        assert_eq!(Some(SplitGroupRef { id: 1, version: 1 }), receipt.split_group);
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::SplitGroupVersion { id: 1, version: 1 }).unwrap();
+        let value: SplitGroupVersionResponse = from_json(&res).unwrap();
+        assert_eq!(split_members(&[("dave", 1), ("erin", 3)]), value.members);
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::SplitGroupsByAdmin { admin: Addr::unchecked("Alice"), start_after: None, limit: None }).unwrap();
+        let value: SplitGroupsResponse = from_json(&res).unwrap();
+        assert_eq!(1, value.groups.len());
+        assert_eq!(2, value.groups[0].version);
+    }
+
+    #[test]
+    fn split_group_with_duplicate_members() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let create_msg = ExecuteMsg::CreateSplitGroup { name: "team".to_string(), members: split_members(&[("dave", 1), ("dave", 3)]) };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), create_msg);
+        match res {
+            Err(ContractError::InvalidSplitGroup {}) => (),
+            _ => panic!("Must return invalid split group error"),
+        }
+    }
+
+    fn create_invoice(deps: DepsMut, id: &str, payer: Option<&str>, split: Vec<SplitMember>) {
+        let create_msg = ExecuteMsg::CreateInvoice {
+            id: id.to_string(),
+            amount: Uint128::from(100u32),
+            denom: "usei".to_string(),
+            payer: payer.map(Addr::unchecked),
+            expiry: mock_env().block.time.plus_seconds(3600),
+            split,
+        };
+        execute(deps, mock_env(), mock_info("dave", &[]), create_msg).expect("dave creates an invoice");
+    }
+
+    fn invoice_ids(deps: Deps, msg: QueryMsg) -> Vec<String> {
+        let value: InvoicesResponse = from_json(query(deps, mock_env(), msg).unwrap()).unwrap();
+        value.invoices.into_iter().map(|i| i.id).collect()
+    }
+
+    #[test]
+    fn invoices_paid_cancelled_and_expired() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        create_invoice(deps.as_mut(), "INV-1", Some("erin"), split_members(&[("dave", 3), ("fred", 1)]));
+        create_invoice(deps.as_mut(), "INV-2", None, vec![]);
+        create_invoice(deps.as_mut(), "INV-3", None, vec![]);
+
+        let create_msg = ExecuteMsg::CreateInvoice {
+            id: "INV-1".to_string(),
+            amount: Uint128::from(5u32),
+            denom: "usei".to_string(),
+            payer: None,
+            expiry: mock_env().block.time.plus_seconds(3600),
+            split: vec![],
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("dave", &[]), create_msg) {
+            Err(ContractError::InvoiceExists { id }) => assert_eq!("INV-1", id),
+            _ => panic!("Must return invoice exists error"),
+        }
+        // Ids are per payee, so another payee can use the same one.
+        let create_msg = ExecuteMsg::CreateInvoice {
+            id: "INV-1".to_string(),
+            amount: Uint128::from(5u32),
+            denom: "usei".to_string(),
+            payer: None,
+            expiry: mock_env().block.time.plus_seconds(3600),
+            split: vec![],
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("erin", &[]), create_msg).unwrap();
+        let create_msg = ExecuteMsg::CreateInvoice {
+            id: String::new(),
+            amount: Uint128::from(5u32),
+            denom: "usei".to_string(),
+            payer: None,
+            expiry: mock_env().block.time.plus_seconds(3600),
+            split: vec![],
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("erin", &[]), create_msg) {
+            Err(ContractError::InvalidInvoiceId { .. }) => (),
+            _ => panic!("Must return invalid invoice id error"),
+        }
+
+        // Only the designated payer can pay, and only with the exact amount.
+        let pay_msg = ExecuteMsg::PayInvoice { payee: Addr::unchecked("dave"), id: "INV-1".to_string() };
+        match execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(100, "usei")), pay_msg.clone()) {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+        match execute(deps.as_mut(), mock_env(), mock_info("erin", &coins(90, "usei")), pay_msg.clone()) {
+            Err(ContractError::InvoicePaymentMismatch { .. }) => (),
+            _ => panic!("Must return payment mismatch error"),
+        }
+        let res = execute(deps.as_mut(), mock_env(), mock_info("erin", &coins(100, "usei")), pay_msg.clone()).unwrap();
+        assert!(res.attributes.contains(&attr("reference", "INV-1")));
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(75u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("fred"), Uint128::from(25u32));
+        match execute(deps.as_mut(), mock_env(), mock_info("erin", &coins(100, "usei")), pay_msg) {
+            Err(ContractError::InvoiceNotOpen {}) => (),
+            _ => panic!("Must return invoice not open error"),
+        }
+
+        // Open invoices can be paid by anyone, with the whole amount going to the payee.
+        let pay_msg = ExecuteMsg::PayInvoice { payee: Addr::unchecked("dave"), id: "INV-2".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("fred", &coins(100, "usei")), pay_msg).unwrap();
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(175u32));
+
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(3600);
+        let expire_msg = ExecuteMsg::ExpireInvoice { payee: Addr::unchecked("dave"), id: "INV-3".to_string() };
+        match execute(deps.as_mut(), mock_env(), mock_info("fred", &[]), expire_msg.clone()) {
+            Err(ContractError::InvoiceNotExpired {}) => (),
+            _ => panic!("Must return invoice not expired error"),
+        }
+        let pay_msg = ExecuteMsg::PayInvoice { payee: Addr::unchecked("dave"), id: "INV-3".to_string() };
+        match execute(deps.as_mut(), later.clone(), mock_info("fred", &coins(100, "usei")), pay_msg) {
+            Err(ContractError::InvoiceExpired {}) => (),
+            _ => panic!("Must return invoice expired error"),
+        }
+        execute(deps.as_mut(), later, mock_info("fred", &[]), expire_msg).unwrap();
+
+        create_invoice(deps.as_mut(), "INV-4", Some("erin"), vec![]);
+        let cancel_msg = ExecuteMsg::CancelInvoice { id: "INV-4".to_string() };
+        // Cancelling looks the id up among the sender's own invoices, where erin has no INV-4.
+        assert!(execute(deps.as_mut(), mock_env(), mock_info("erin", &[]), cancel_msg).is_err());
+        let cancel_msg = ExecuteMsg::CancelInvoice { id: "INV-1".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("erin", &[]), cancel_msg).unwrap();
+        let cancel_msg = ExecuteMsg::CancelInvoice { id: "INV-4".to_string() };
+        execute(deps.as_mut(), mock_env(), mock_info("dave", &[]), cancel_msg).unwrap();
+
+        let by_payee = QueryMsg::InvoicesByPayee { payee: Addr::unchecked("dave"), start_after: Some("INV-1".to_string()), limit: Some(2) };
+        assert_eq!(vec!["INV-2", "INV-3"], invoice_ids(deps.as_ref(), by_payee));
+        let by_payer = QueryMsg::InvoicesByPayer { payer: Addr::unchecked("erin"), start_after: None, limit: None };
+        assert_eq!(vec!["INV-1", "INV-4"], invoice_ids(deps.as_ref(), by_payer));
+        let by_payer = QueryMsg::InvoicesByPayer { payer: Addr::unchecked("fred"), start_after: None, limit: None };
+        assert_eq!(vec!["INV-2"], invoice_ids(deps.as_ref(), by_payer));
+        for (status, expected) in [
+            (InvoiceStatus::Paid, vec!["INV-1", "INV-2"]),
+            (InvoiceStatus::Expired, vec!["INV-3"]),
+            (InvoiceStatus::Cancelled, vec!["INV-4", "INV-1"]),
+            (InvoiceStatus::Open, vec![]),
+        ] {
+            let by_status = QueryMsg::InvoicesByStatus { status, start_after: None, limit: None };
+            assert_eq!(expected, invoice_ids(deps.as_ref(), by_status));
+        }
+        let start_after = Some((Addr::unchecked("dave"), "INV-4".to_string()));
+        let by_status = QueryMsg::InvoicesByStatus { status: InvoiceStatus::Cancelled, start_after, limit: None };
+        assert_eq!(vec!["INV-1"], invoice_ids(deps.as_ref(), by_status));
+
+        let value: InvoiceResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Invoice { payee: Addr::unchecked("dave"), id: "INV-2".to_string() }).unwrap()).unwrap();
+        assert_eq!(Some(Addr::unchecked("fred")), value.paid_by);
+    }
+
+    fn hold_ids(deps: Deps, env: cosmwasm_std::Env, msg: QueryMsg) -> Vec<u64> {
+        let value: HoldsResponse = from_json(query(deps, env, msg).unwrap()).unwrap();
+        value.holds.into_iter().map(|h| h.id).collect()
+    }
+
+    #[test]
+    fn holds_reserve_balance_until_captured() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("Bob"),
+            address2: Addr::unchecked("Carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1000, "usei")), transfer_msg).unwrap();
+
+        let expiry = mock_env().block.time.plus_seconds(3600);
+        let hold_msg = ExecuteMsg::AuthorizeHold { merchant: Addr::unchecked("dave"), amount: Uint128::from(300u32), expiry };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), hold_msg).unwrap();
+
+        let withdraw_msg = ExecuteMsg::Withdraw { amount: Uint128::from(300u32) };
+        match execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), withdraw_msg) {
+            Err(ContractError::FundsOnHold {}) => (),
+            _ => panic!("Must return funds on hold error"),
+        }
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::Withdraw { amount: Uint128::from(200u32) }).unwrap();
+        let hold_msg = ExecuteMsg::AuthorizeHold { merchant: Addr::unchecked("dave"), amount: Uint128::from(1u32), expiry };
+        match execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), hold_msg) {
+            Err(ContractError::InsufficientBalance {}) => (),
+            _ => panic!("Must return insufficient balance error"),
+        }
+
+        let by_account = QueryMsg::HoldsByAccount { account: Addr::unchecked("Bob"), start_after: None, limit: None };
+        assert_eq!(vec![1], hold_ids(deps.as_ref(), mock_env(), by_account));
+        let by_merchant = QueryMsg::HoldsByMerchant { merchant: Addr::unchecked("dave"), start_after: None, limit: None };
+        assert_eq!(vec![1], hold_ids(deps.as_ref(), mock_env(), by_merchant.clone()));
+
+        match execute(deps.as_mut(), mock_env(), mock_info("erin", &[]), ExecuteMsg::CaptureHold { id: 1, amount: Uint128::from(100u32) }) {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+        match execute(deps.as_mut(), mock_env(), mock_info("dave", &[]), ExecuteMsg::CaptureHold { id: 1, amount: Uint128::from(301u32) }) {
+            Err(ContractError::CaptureExceedsHold { amount }) => assert_eq!(Uint128::from(300u32), amount),
+            _ => panic!("Must return capture exceeds hold error"),
+        }
+        let res = execute(deps.as_mut(), mock_env(), mock_info("dave", &[]), ExecuteMsg::CaptureHold { id: 1, amount: Uint128::from(120u32) }).unwrap();
+        assert!(res.attributes.contains(&attr("released", "180")));
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(120u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(180u32));
+        assert!(hold_ids(deps.as_ref(), mock_env(), by_merchant).is_empty());
+
+        // An expired hold no longer reserves funds and can only be released.
+        let hold_msg = ExecuteMsg::AuthorizeHold { merchant: Addr::unchecked("dave"), amount: Uint128::from(180u32), expiry: mock_env().block.time.plus_seconds(10) };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), hold_msg).unwrap();
+        match execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::ReleaseHold { id: 2 }) {
+            Err(ContractError::HoldNotExpired {}) => (),
+            _ => panic!("Must return hold not expired error"),
+        }
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(10);
+        let by_account = QueryMsg::HoldsByAccount { account: Addr::unchecked("Bob"), start_after: None, limit: None };
+        assert!(hold_ids(deps.as_ref(), later.clone(), by_account).is_empty());
+        match execute(deps.as_mut(), later.clone(), mock_info("dave", &[]), ExecuteMsg::CaptureHold { id: 2, amount: Uint128::from(1u32) }) {
+            Err(ContractError::HoldExpired {}) => (),
+            _ => panic!("Must return hold expired error"),
+        }
+        assert!(query(deps.as_ref(), later.clone(), QueryMsg::Hold { id: 2 }).is_ok());
+        // The next debit deletes the expired hold, so there is nothing left to release.
+        execute(deps.as_mut(), later.clone(), mock_info("Bob", &[]), ExecuteMsg::Withdraw { amount: Uint128::from(180u32) }).unwrap();
+        assert!(query(deps.as_ref(), later.clone(), QueryMsg::Hold { id: 2 }).is_err());
+        assert!(execute(deps.as_mut(), later, mock_info("Bob", &[]), ExecuteMsg::ReleaseHold { id: 2 }).is_err());
+    }
+
+    fn create_campaign(deps: DepsMut, target: u32, split: Vec<SplitMember>) {
+        let create_msg = ExecuteMsg::CreateCampaign {
+            target: Uint128::from(target),
+            deadline: mock_env().block.time.plus_seconds(3600),
+            split,
+        };
+        execute(deps, mock_env(), mock_info("dave", &[]), create_msg).expect("dave creates a campaign");
+    }
+
+    fn contribute(deps: DepsMut, id: u64, contributor: &str, amount: u128) {
+        execute(deps, mock_env(), mock_info(contributor, &coins(amount, "usei")), ExecuteMsg::Contribute { id }).unwrap();
+    }
+
+    #[test]
+    fn campaigns_pay_out_or_refund() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        create_campaign(deps.as_mut(), 100, split_members(&[("dave", 1), ("erin", 1)]));
+        create_campaign(deps.as_mut(), 1000, vec![]);
+        contribute(deps.as_mut(), 1, "Alice", 60);
+        contribute(deps.as_mut(), 1, "Bob", 50);
+        contribute(deps.as_mut(), 2, "Alice", 300);
+        contribute(deps.as_mut(), 2, "Alice", 200);
+
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(3600);
+        match execute(deps.as_mut(), later.clone(), mock_info("Bob", &coins(10, "usei")), ExecuteMsg::Contribute { id: 1 }) {
+            Err(ContractError::CampaignClosed {}) => (),
+            _ => panic!("Must return campaign closed error"),
+        }
+        match execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), ExecuteMsg::FinalizeCampaign { id: 1 }) {
+            Err(ContractError::CampaignNotEnded {}) => (),
+            _ => panic!("Must return campaign not ended error"),
+        }
+
+        // The first campaign met its target and is credited to the split.
+        let res = execute(deps.as_mut(), later.clone(), mock_info("Bob", &[]), ExecuteMsg::FinalizeCampaign { id: 1 }).unwrap();
+        assert!(res.attributes.contains(&attr("status", "funded")));
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(55u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("erin"), Uint128::from(55u32));
+        // The payout is sent by dave, the creator, rather than by the contract.
+        let msg = QueryMsg::AccountHistory { address: Addr::unchecked("erin"), start_after: None, limit: None, order: None };
+        let value: AccountHistoryResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![Some(Addr::unchecked("dave"))], value.entries.into_iter().map(|e| e.counterparty).collect::<Vec<_>>());
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::AccountSummary { address: Addr::unchecked("cosmos2contract") }).unwrap();
+        let value: AccountSummaryResponse = from_json(res).unwrap();
+        assert_eq!(0, value.transfers_sent);
+        match execute(deps.as_mut(), later.clone(), mock_info("Alice", &[]), ExecuteMsg::ClaimRefund { id: 1 }) {
+            Err(ContractError::CampaignNotFailed {}) => (),
+            _ => panic!("Must return campaign not failed error"),
+        }
+
+        // The second fell short, so Alice gets her pledges back, once.
+        let res = execute(deps.as_mut(), later.clone(), mock_info("Alice", &[]), ExecuteMsg::ClaimRefund { id: 2 }).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send { to_address: "Alice".to_string(), amount: coins(500, "usei") })
+        );
+        match execute(deps.as_mut(), later.clone(), mock_info("Alice", &[]), ExecuteMsg::ClaimRefund { id: 2 }) {
+            Err(ContractError::NoPledge {}) => (),
+            _ => panic!("Must return no pledge error"),
+        }
+        match execute(deps.as_mut(), later, mock_info("Bob", &[]), ExecuteMsg::FinalizeCampaign { id: 2 }) {
+            Err(ContractError::CampaignFinalized {}) => (),
+            _ => panic!("Must return campaign finalized error"),
+        }
+
+        let value: CampaignsResponse = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Campaigns { start_after: Some(1), limit: None }).unwrap()).unwrap();
+        assert_eq!(1, value.campaigns.len());
+        assert_eq!(CampaignStatus::Failed, value.campaigns[0].status);
+        assert_eq!(Uint128::from(500u32), value.campaigns[0].raised);
+
+        let msg = QueryMsg::CampaignPledges { id: 1, start_after: Some(Addr::unchecked("Alice")), limit: None };
+        let value: PledgesResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(
+            vec![PledgeResponse { campaign_id: 1, contributor: Addr::unchecked("Bob"), amount: Uint128::from(50u32), refunded: false }],
+            value.pledges
+        );
+        let msg = QueryMsg::ContributorPledges { contributor: Addr::unchecked("Alice"), start_after: None, limit: Some(1) };
+        let value: PledgesResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![(1, Uint128::from(60u32))], value.pledges.iter().map(|p| (p.campaign_id, p.amount)).collect::<Vec<_>>());
+        let msg = QueryMsg::ContributorPledges { contributor: Addr::unchecked("Alice"), start_after: Some(1), limit: None };
+        let value: PledgesResponse = from_json(query(deps.as_ref(), mock_env(), msg).unwrap()).unwrap();
+        assert_eq!(vec![(2, true)], value.pledges.iter().map(|p| (p.campaign_id, p.refunded)).collect::<Vec<_>>());
+    }
+
+    fn create_standing_order(deps: DepsMut, payer: &str, deposit: u128, end_after: u64) {
+        let create_msg = ExecuteMsg::CreateStandingOrder {
+            recipients: split_members(&[("dave", 1), ("erin", 1)]),
+            amount: Uint128::from(100u32),
+            interval: 100,
+            end: mock_env().block.time.plus_seconds(end_after),
+        };
+        execute(deps, mock_env(), mock_info(payer, &coins(deposit, "usei")), create_msg).unwrap();
+    }
+
+    fn due_order_ids(deps: Deps, env: cosmwasm_std::Env) -> Vec<u64> {
+        let value: StandingOrdersResponse = from_json(query(deps, env, QueryMsg::DueStandingOrders { limit: None }).unwrap()).unwrap();
+        value.orders.into_iter().map(|o| o.id).collect()
+    }
+
+    #[test]
+    fn standing_orders_paid_by_keepers() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        create_standing_order(deps.as_mut(), "Alice", 1000, 250);
+        create_standing_order(deps.as_mut(), "Bob", 115, 1000);
+        create_standing_order(deps.as_mut(), "Carl", 500, 1000);
+
+        // Raising the bounty does not make existing orders pay more per period.
+        let bounty_msg = ExecuteMsg::UpdateKeeperBounty { bounty: Uint128::from(50u32) };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), bounty_msg).unwrap();
+
+        let execute_msg = ExecuteMsg::ExecuteDue { ids: vec![1, 2] };
+        match execute(deps.as_mut(), mock_env(), mock_info("fred", &[]), execute_msg.clone()) {
+            Err(ContractError::NothingDue {}) => (),
+            _ => panic!("Must return nothing due error"),
+        }
+        assert!(due_order_ids(deps.as_ref(), mock_env()).is_empty());
+
+        // Two periods have passed on the first order, after which it has ended.  The second runs out of deposit.
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(210);
+        assert_eq!(vec![1, 2, 3], due_order_ids(deps.as_ref(), later.clone()));
+        let res = execute(deps.as_mut(), later.clone(), mock_info("fred", &[]), execute_msg).unwrap();
+        assert!(res.attributes.contains(&attr("payments", "3")));
+        assert!(res.attributes.contains(&attr("transfer_ids", "1,2,3")));
+        assert!(res.attributes.contains(&attr("bounty", "30")));
+        assert!(res.attributes.contains(&attr("ended", "1,2")));
+        assert_eq!(
+            vec![
+                CosmosMsg::Bank(BankMsg::Send { to_address: "Alice".to_string(), amount: coins(780, "usei") }),
+                CosmosMsg::Bank(BankMsg::Send { to_address: "Bob".to_string(), amount: coins(5, "usei") }),
+            ],
+            res.messages.into_iter().map(|m| m.msg).collect::<Vec<_>>()
+        );
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(150u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("erin"), Uint128::from(150u32));
+        assert_balance(deps.as_ref(), Addr::unchecked("fred"), Uint128::from(30u32));
+        assert_eq!(vec![3], due_order_ids(deps.as_ref(), later));
+
+        // Each payment keeps its receipt, with the keeper bounty as its fee.
+        let receipt: TransferReceipt = from_json(query(deps.as_ref(), mock_env(), QueryMsg::Receipt { id: 3 }).unwrap()).unwrap();
+        assert_eq!(Uint128::from(10u32), receipt.fee);
+        assert_eq!(vec![Uint128::from(50u32); 2], receipt.credits.iter().map(|c| c.amount).collect::<Vec<_>>());
+
+        match execute(deps.as_mut(), mock_env(), mock_info("Alice", &[]), ExecuteMsg::CancelStandingOrder { id: 3 }) {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Carl", &[]), ExecuteMsg::CancelStandingOrder { id: 3 }).unwrap();
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Bank(BankMsg::Send { to_address: "Carl".to_string(), amount: coins(500, "usei") })
+        );
+    }
+
+    #[test]
+    fn standing_order_catches_up_over_several_calls() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let create_msg = ExecuteMsg::CreateStandingOrder {
+            recipients: split_members(&[("dave", 1), ("erin", 1)]),
+            amount: Uint128::from(100u32),
+            interval: u64::MAX / 1_000_000,
+            end: mock_env().block.time.plus_seconds(1000),
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("Alice", &coins(1000, "usei")), create_msg) {
+            Err(ContractError::InvalidStandingOrderInterval { .. }) => (),
+            _ => panic!("Must return invalid interval error"),
+        }
+
+        // Twenty periods are due, but only twelve are paid per call.
+        create_standing_order(deps.as_mut(), "Alice", 3000, 5000);
+        let mut later = mock_env();
+        later.block.time = later.block.time.plus_seconds(2000);
+        let execute_msg = ExecuteMsg::ExecuteDue { ids: vec![1] };
+        let res = execute(deps.as_mut(), later.clone(), mock_info("fred", &[]), execute_msg.clone()).unwrap();
+        assert!(res.attributes.contains(&attr("payments", "12")));
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(600u32));
+        assert_eq!(vec![1], due_order_ids(deps.as_ref(), later.clone()));
+
+        let res = execute(deps.as_mut(), later.clone(), mock_info("fred", &[]), execute_msg).unwrap();
+        assert!(res.attributes.contains(&attr("payments", "8")));
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(1000u32));
+        assert!(due_order_ids(deps.as_ref(), later).is_empty());
+    }
+
+    fn ledger_counterparty() -> LedgerCounterparty {
+        // The connection and port used by mock_ibc_channel.
+        LedgerCounterparty { connection_id: "connection-2".to_string(), port_id: "their_port".to_string() }
+    }
+
+    fn trust_ledger_counterparty(deps: DepsMut) {
+        let update_msg = ExecuteMsg::UpdateLedgerCounterparties { add: vec![ledger_counterparty()], remove: vec![] };
+        execute(deps, mock_env(), mock_info("creator", &[]), update_msg).expect("Owner trusts the sister deployment");
+    }
+
+    fn connect_ledger_channel(mut deps: DepsMut) {
+        trust_ledger_counterparty(deps.branch());
+        let connect_msg = mock_ibc_channel_connect_ack("channel-3", IbcOrder::Unordered, IBC_APP_VERSION);
+        ibc_channel_connect(deps, mock_env(), connect_msg).expect("Ledger channel connects");
+    }
+
+    #[test]
+    fn ledger_channel_handshake() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), mock_ibc_channel_open_try("channel-3", IbcOrder::Unordered, "ics20-1"));
+        match res {
+            Err(ContractError::InvalidIbcVersion { .. }) => (),
+            _ => panic!("Must return invalid version error"),
+        }
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), mock_ibc_channel_open_try("channel-3", IbcOrder::Ordered, IBC_APP_VERSION));
+        match res {
+            Err(ContractError::InvalidIbcOrder {}) => (),
+            _ => panic!("Must return invalid order error"),
+        }
+
+        trust_ledger_counterparty(deps.as_mut());
+        let open_msg = mock_ibc_channel_open_try("channel-3", IbcOrder::Unordered, IBC_APP_VERSION);
+        let res = ibc_channel_open(deps.as_mut(), mock_env(), open_msg).unwrap();
+        assert_eq!(IBC_APP_VERSION, res.unwrap().version);
+        connect_ledger_channel(deps.as_mut());
+
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::LedgerChannels {}).unwrap();
+        let value: LedgerChannelsResponse = from_json(&res).unwrap();
+        assert_eq!(vec!["channel-3".to_string()], value.channels);
+    }
+
+    #[test]
+    fn ledger_channel_refuses_unknown_counterparty() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+
+        // Only the owner manages the allowlist.
+        let update_msg = ExecuteMsg::UpdateLedgerCounterparties { add: vec![ledger_counterparty()], remove: vec![] };
+        match execute(deps.as_mut(), mock_env(), mock_info("Mallory", &[]), update_msg) {
+            Err(ContractError::Unauthorized {}) => (),
+            _ => panic!("Must return unauthorized error"),
+        }
+
+        // A counterparty speaking the right version is still refused until the owner trusts it.
+        let open_msg = mock_ibc_channel_open_try("channel-3", IbcOrder::Unordered, IBC_APP_VERSION);
+        match ibc_channel_open(deps.as_mut(), mock_env(), open_msg) {
+            Err(ContractError::UntrustedLedgerCounterparty { .. }) => (),
+            _ => panic!("Must return untrusted counterparty error"),
+        }
+        let connect_msg = mock_ibc_channel_connect_ack("channel-3", IbcOrder::Unordered, IBC_APP_VERSION);
+        match ibc_channel_connect(deps.as_mut(), mock_env(), connect_msg) {
+            Err(ContractError::UntrustedLedgerCounterparty { .. }) => (),
+            _ => panic!("Must return untrusted counterparty error"),
+        }
+
+        // Packets on a channel that never connected are rejected and credit nobody.
+        let packet = LedgerPacket::Credit {
+            sender: "remote_mallory".to_string(),
+            credits: vec![LedgerCredit { address: "mallory".to_string(), amount: Uint128::from(1000u32) }],
+        };
+        assert!(ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).is_err());
+
+        // Removing a trusted counterparty cuts off channels it already connected.
+        connect_ledger_channel(deps.as_mut());
+        let update_msg = ExecuteMsg::UpdateLedgerCounterparties { add: vec![], remove: vec![ledger_counterparty()] };
+        execute(deps.as_mut(), mock_env(), mock_info("creator", &[]), update_msg).unwrap();
+        assert!(ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).is_err());
+        assert_balance(deps.as_ref(), Addr::unchecked("mallory"), Uint128::zero());
+    }
+
+    #[test]
+    fn ledger_credits_sent_and_refunded_on_error_ack() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        connect_ledger_channel(deps.as_mut());
+        fund_bob_and_allow_channel(deps.as_mut());
+
+        let credits = vec![LedgerCredit { address: "dave".to_string(), amount: Uint128::from(300u32) }];
+        let send_msg = ExecuteMsg::SendLedgerCredits { channel_id: "channel-3".to_string(), credits: credits.clone(), timeout: 600 };
+        let res = execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), send_msg).expect("Bob sends credits to the sister chain");
+        let packet = LedgerPacket::Credit { sender: "Bob".to_string(), credits };
+        assert_eq!(
+            res.messages[0].msg,
+            CosmosMsg::Ibc(IbcMsg::SendPacket {
+                channel_id: "channel-3".to_string(),
+                data: to_json_binary(&packet).unwrap(),
+                timeout: IbcTimeout::with_timestamp(mock_env().block.time.plus_seconds(600)),
+            })
+        );
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(200u32));
+        // The usei stays here, escrowed as the backing for the credits on the other side.
+        assert_eq!(Uint128::from(300u32), ledger_amount(deps.as_ref(), QueryMsg::LedgerEscrow { channel_id: "channel-3".to_string() }));
+
+        // The error acknowledgement wasmd writes when the other side fails the packet.
+        let error_ack = IbcAcknowledgement::new(br#"{"error":"ABCI code: 5: error handling packet: see events for details"}"#.as_slice());
+        ibc_packet_ack(deps.as_mut(), mock_env(), mock_ibc_packet_ack("channel-3", &packet, error_ack).unwrap()).unwrap();
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+        assert_eq!(Uint128::zero(), ledger_amount(deps.as_ref(), QueryMsg::LedgerEscrow { channel_id: "channel-3".to_string() }));
+
+        // A timed out credit packet is refunded too.
+        let send_msg = ExecuteMsg::SendLedgerCredits { channel_id: "channel-3".to_string(), credits: packet_credits(&packet), timeout: 600 };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), send_msg).unwrap();
+        ibc_packet_timeout(deps.as_mut(), mock_env(), mock_ibc_packet_timeout("channel-3", &packet).unwrap()).unwrap();
+        assert_balance(deps.as_ref(), Addr::unchecked("Bob"), Uint128::from(500u32));
+        assert_eq!(Uint128::zero(), ledger_amount(deps.as_ref(), QueryMsg::LedgerEscrow { channel_id: "channel-3".to_string() }));
+    }
+
+    fn ledger_amount(deps: Deps, msg: QueryMsg) -> Uint128 {
+        let value: GetBalanceResponse = from_json(query(deps, mock_env(), msg).unwrap()).unwrap();
+        value.balance
+    }
+
+    fn packet_credits(packet: &LedgerPacket) -> Vec<LedgerCredit> {
+        match packet {
+            LedgerPacket::Credit { credits, .. } | LedgerPacket::Return { credits, .. } => credits.clone(),
+            LedgerPacket::Balance { .. } => vec![],
+        }
+    }
+
+    fn dave_vouchers(deps: Deps) -> Uint128 {
+        ledger_amount(deps, QueryMsg::LedgerVouchers { channel_id: "channel-3".to_string(), address: Addr::unchecked("dave") })
+    }
+
+    #[test]
+    fn ledger_vouchers_returned_against_escrow() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        connect_ledger_channel(deps.as_mut());
+        fund_bob_and_allow_channel(deps.as_mut());
+
+        // Bob escrows 300 usei for dave on the sister chain.
+        let credits = vec![LedgerCredit { address: "dave".to_string(), amount: Uint128::from(300u32) }];
+        let send_msg = ExecuteMsg::SendLedgerCredits { channel_id: "channel-3".to_string(), credits, timeout: 600 };
+        execute(deps.as_mut(), mock_env(), mock_info("Bob", &[]), send_msg).unwrap();
+
+        // The sister chain returns more than was ever escrowed, which is refused.
+        let packet = LedgerPacket::Return {
+            sender: "remote_dave".to_string(),
+            credits: vec![LedgerCredit { address: "dave".to_string(), amount: Uint128::from(301u32) }],
+        };
+        assert!(ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).is_err());
+
+        // Returning vouchers releases the escrow into the ledger, where it can be withdrawn.
+        let packet = LedgerPacket::Return {
+            sender: "remote_dave".to_string(),
+            credits: vec![LedgerCredit { address: "dave".to_string(), amount: Uint128::from(200u32) }],
+        };
+        ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).unwrap();
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(200u32));
+        assert_eq!(Uint128::from(100u32), ledger_amount(deps.as_ref(), QueryMsg::LedgerEscrow { channel_id: "channel-3".to_string() }));
+
+        // Vouchers held here can only be sent back; a failed return restores them.
+        let packet = LedgerPacket::Credit {
+            sender: "remote_erin".to_string(),
+            credits: vec![LedgerCredit { address: "dave".to_string(), amount: Uint128::from(50u32) }],
+        };
+        ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).unwrap();
+        assert_eq!(Uint128::from(50u32), dave_vouchers(deps.as_ref()));
+        let return_msg = ExecuteMsg::ReturnLedgerCredits {
+            channel_id: "channel-3".to_string(),
+            credits: vec![LedgerCredit { address: "remote_erin".to_string(), amount: Uint128::from(60u32) }],
+            timeout: 600,
+        };
+        match execute(deps.as_mut(), mock_env(), mock_info("dave", &[]), return_msg) {
+            Err(ContractError::InsufficientBalance {}) => (),
+            _ => panic!("Must return insufficient balance error"),
+        }
+        let credits = vec![LedgerCredit { address: "remote_erin".to_string(), amount: Uint128::from(50u32) }];
+        let return_msg = ExecuteMsg::ReturnLedgerCredits { channel_id: "channel-3".to_string(), credits: credits.clone(), timeout: 600 };
+        execute(deps.as_mut(), mock_env(), mock_info("dave", &[]), return_msg).unwrap();
+        assert_eq!(Uint128::zero(), dave_vouchers(deps.as_ref()));
+        let packet = LedgerPacket::Return { sender: "dave".to_string(), credits };
+        ibc_packet_timeout(deps.as_mut(), mock_env(), mock_ibc_packet_timeout("channel-3", &packet).unwrap()).unwrap();
+        assert_eq!(Uint128::from(50u32), dave_vouchers(deps.as_ref()));
+        assert_balance(deps.as_ref(), Addr::unchecked("dave"), Uint128::from(200u32));
+    }
+
+    #[test]
+    fn ledger_packets_received_from_sister_deployment() {
+        let mut deps = mock_dependencies();
+        mock_init(deps.as_mut());
+        connect_ledger_channel(deps.as_mut());
+
+        let packet = LedgerPacket::Credit {
+            sender: "remote_alice".to_string(),
+            credits: vec![
+                LedgerCredit { address: "bob".to_string(), amount: Uint128::from(100u32) },
+                LedgerCredit { address: "carl".to_string(), amount: Uint128::from(50u32) },
+            ],
+        };
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).unwrap();
+        let ack: LedgerAck = from_json(&res.acknowledgement).unwrap();
+        assert_eq!(LedgerAck::Result(to_json_binary(&true).unwrap()), ack);
+        // Received credits are vouchers backed by the sister chain's escrow, not withdrawable usei.
+        assert_balance(deps.as_ref(), Addr::unchecked("bob"), Uint128::zero());
+        let vouchers = ledger_amount(deps.as_ref(), QueryMsg::LedgerVouchers { channel_id: "channel-3".to_string(), address: Addr::unchecked("bob") });
+        assert_eq!(Uint128::from(100u32), vouchers);
+
+        // An invalid account fails the whole packet, which the chain acknowledges with an error.
+        let packet = LedgerPacket::Credit {
+            sender: "remote_alice".to_string(),
+            credits: vec![
+                LedgerCredit { address: "dave".to_string(), amount: Uint128::from(100u32) },
+                LedgerCredit { address: "x".to_string(), amount: Uint128::from(50u32) },
+            ],
+        };
+        assert!(ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).is_err());
+        assert_eq!(Uint128::zero(), dave_vouchers(deps.as_ref()));
+
+        // Balance queries are answered in the ack, and the sender caches the answer.
+        fund_bob_and_allow_channel(deps.as_mut());
+        let packet = LedgerPacket::Balance { address: "Bob".to_string() };
+        let res = ibc_packet_receive(deps.as_mut(), mock_env(), mock_ibc_packet_recv("channel-3", &packet).unwrap()).unwrap();
+        let ack = IbcAcknowledgement::new(res.acknowledgement);
+        ibc_packet_ack(deps.as_mut(), mock_env(), mock_ibc_packet_ack("channel-3", &packet, ack).unwrap()).unwrap();
+        let res = query(deps.as_ref(), mock_env(), QueryMsg::RemoteBalance { channel_id: "channel-3".to_string(), address: "Bob".to_string() }).unwrap();
+        let value: RemoteBalanceResponse = from_json(&res).unwrap();
+        assert_eq!(Some(Uint128::from(500u32)), value.balance);
+    }
+}
+
+#[cfg(test)]
+mod multi_test_module {
+    use cosmwasm_std::{
+        coins, from_json, to_json_binary, Addr, BankMsg, Binary, Coin, Decimal, Deps, DepsMut, Empty, Env, MessageInfo, Response, StdError, StdResult,
+        Uint128
+    };
+    use cosmwasm_std::Validator;
+    use cw_storage_plus::Item;
+    use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor, StakingInfo};
+
+    use crate::contract::{execute, instantiate, query, reply, sudo};
+    use crate::msg::{
+        AccountHistoryResponse, AssetInfo, DelegationResponse, ExecuteMsg, GetBalanceResponse, InstantiateMsg, OraclePriceResponse, OracleQueryMsg, PairExecuteMsg, QueryMsg,
+        StakingStateResponse
+    };
+    use crate::state::{EntryKind, TransferReceipt};
+
+    // A pair that swaps native usei into twice as much uusdc.
+    fn mock_pair_execute(_deps: DepsMut, _env: Env, info: MessageInfo, msg: PairExecuteMsg) -> StdResult<Response> {
+        match msg {
+            PairExecuteMsg::Swap { offer_asset, .. } => {
+                if offer_asset.info != (AssetInfo::NativeToken { denom: "usei".to_string() })
+                    || info.funds != coins(offer_asset.amount.u128(), "usei")
+                {
+                    return Err(StdError::generic_err("offer asset does not match funds"));
+                }
+                Ok(Response::new().add_message(BankMsg::Send {
+                    to_address: info.sender.to_string(),
+                    amount: coins(offer_asset.amount.u128() * 2, "uusdc"),
+                }))
+            }
+        }
+    }
+
+    fn mock_pair_instantiate(_deps: DepsMut, _env: Env, _info: MessageInfo, _msg: Empty) -> StdResult<Response> {
+        Ok(Response::new())
+    }
+
+    fn mock_pair_query(_deps: Deps, _env: Env, _msg: Empty) -> StdResult<Binary> {
+        Err(StdError::generic_err("no queries"))
+    }
+
+    fn mock_pair() -> Box<dyn Contract<Empty>> {
+        Box::new(ContractWrapper::new(mock_pair_execute, mock_pair_instantiate, mock_pair_query))
+    }
+
+    fn ledger_contract() -> Box<dyn Contract<Empty>> {
+        Box::new(ContractWrapper::new(execute, instantiate, query).with_reply(reply).with_sudo(sudo))
+    }
+
+    // Instantiates the ledger with alice holding 1000 usei and the pair holding 10000 uusdc.  Returns (ledger, pair).
+    fn setup(app: &mut App) -> (Addr, Addr) {
+        let ledger_id = app.store_code(ledger_contract());
+        let pair_id = app.store_code(mock_pair());
+        let owner = Addr::unchecked("owner");
+
+        let ledger = app.instantiate_contract(ledger_id, owner.clone(), &InstantiateMsg {}, &[], "ledger", None).unwrap();
+        let pair = app.instantiate_contract(pair_id, owner.clone(), &Empty {}, &[], "pair", None).unwrap();
+        app.send_tokens(Addr::unchecked("bank"), pair.clone(), &coins(10000, "uusdc")).unwrap();
+
+        let update_msg = ExecuteMsg::UpdateSwapPair { offer_denom: "usei".to_string(), ask_denom: "uusdc".to_string(), pair: Some(pair.clone()) };
+        app.execute_contract(owner, ledger.clone(), &update_msg, &[]).unwrap();
+        (ledger, pair)
+    }
+
+    fn mock_app() -> App {
+        AppBuilder::new().build(|router, _, storage| {
+            router.bank.init_balance(storage, &Addr::unchecked("alice"), coins(1000, "usei")).unwrap();
+            router.bank.init_balance(storage, &Addr::unchecked("bank"), coins(10000, "uusdc")).unwrap();
+        })
+    }
+
+    fn ledger_balance(app: &App, ledger: &Addr, address: &str) -> Uint128 {
+        let res: GetBalanceResponse = app.wrap().query_wasm_smart(ledger, &QueryMsg::GetBalance { address: Addr::unchecked(address) }).unwrap();
+        res.balance
+    }
+
+    #[test]
+    fn withdraw_and_swap_through_pair() {
+        let mut app = mock_app();
+        let (ledger, _pair) = setup(&mut app);
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("bob"),
+            address2: Addr::unchecked("carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(1000, "usei")).unwrap();
+
+        let swap_msg = ExecuteMsg::WithdrawAndSwap {
+            amount: Uint128::from(200u32),
+            denom: "usei".to_string(),
+            ask_denom: "uusdc".to_string(),
+            min_out: Uint128::from(400u32),
+        };
+        app.execute_contract(Addr::unchecked("bob"), ledger.clone(), &swap_msg, &[]).expect("Bob swaps 200 usei");
+
+        assert_eq!(Coin::new(400, "uusdc"), app.wrap().query_balance("bob", "uusdc").unwrap());
+        assert_eq!(Uint128::from(300u32), ledger_balance(&app, &ledger, "bob"));
+        assert_eq!(Uint128::zero(), app.wrap().query_balance(&ledger, "uusdc").unwrap().amount);
+    }
+
+    #[test]
+    fn withdraw_and_swap_below_minimum_reverts() {
+        let mut app = mock_app();
+        let (ledger, _pair) = setup(&mut app);
+
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("bob"),
+            address2: Addr::unchecked("carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(1000, "usei")).unwrap();
+
+        let swap_msg = ExecuteMsg::WithdrawAndSwap {
+            amount: Uint128::from(200u32),
+            denom: "usei".to_string(),
+            ask_denom: "uusdc".to_string(),
+            min_out: Uint128::from(401u32),
+        };
+        let err = app.execute_contract(Addr::unchecked("bob"), ledger.clone(), &swap_msg, &[]).unwrap_err();
+        assert!(err.root_cause().to_string().contains("less than the minimum"));
+
+        // The debit is rolled back together with the swap.
+        assert_eq!(Uint128::from(500u32), ledger_balance(&app, &ledger, "bob"));
+        assert_eq!(Uint128::zero(), app.wrap().query_balance("bob", "uusdc").unwrap().amount);
+    }
+
+    const MOCK_PRICE: Item<OraclePriceResponse> = Item::new("price");
+
+    // An oracle that reports whatever price it was last given.
+    fn mock_oracle_execute(deps: DepsMut, _env: Env, _info: MessageInfo, msg: OraclePriceResponse) -> StdResult<Response> {
+        MOCK_PRICE.save(deps.storage, &msg)?;
+        Ok(Response::new())
+    }
+
+    fn mock_oracle_query(deps: Deps, _env: Env, msg: OracleQueryMsg) -> StdResult<Binary> {
+        match msg {
+            OracleQueryMsg::Price { .. } => to_json_binary(&MOCK_PRICE.load(deps.storage)?),
+        }
+    }
+
+    fn mock_oracle() -> Box<dyn Contract<Empty>> {
+        Box::new(ContractWrapper::new(mock_oracle_execute, mock_oracle_execute, mock_oracle_query))
+    }
+
+    // Prices usei at 0.5 micro-USD, with prices accepted for 60 seconds.  Returns (ledger, oracle).
+    fn setup_oracle(app: &mut App) -> (Addr, Addr) {
+        let (ledger, _pair) = setup(app);
+        let oracle_id = app.store_code(mock_oracle());
+        let price = OraclePriceResponse { price: Decimal::percent(50), updated_at: app.block_info().time };
+        let oracle = app.instantiate_contract(oracle_id, Addr::unchecked("owner"), &price, &[], "oracle", None).unwrap();
+
+        let update_msg = ExecuteMsg::UpdateOracle { oracle: oracle.clone(), max_price_age: 60 };
+        app.execute_contract(Addr::unchecked("owner"), ledger.clone(), &update_msg, &[]).unwrap();
+        (ledger, oracle)
+    }
+
+    fn transfer_usd_msg(usd_amount: u32, belief_price: Decimal) -> ExecuteMsg {
+        ExecuteMsg::TransferUsd {
+            address1: Addr::unchecked("bob"),
+            address2: Addr::unchecked("carl"),
+            usd_amount: Uint128::from(usd_amount),
+            belief_price,
+            max_slippage: Decimal::percent(1),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        }
+    }
+
+    #[test]
+    fn transfer_usd_converts_and_refunds_excess() {
+        let mut app = mock_app();
+        let (ledger, _oracle) = setup_oracle(&mut app);
+
+        // 300 micro-USD at 0.5 micro-USD per usei is 600 usei; alice sends 1000 and gets 400 back.
+        let res = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_usd_msg(300, Decimal::percent(50)), &coins(1000, "usei"))
+            .expect("alice pays 300 micro-USD");
+        assert_eq!(Uint128::from(300u32), ledger_balance(&app, &ledger, "bob"));
+        assert_eq!(Uint128::from(300u32), ledger_balance(&app, &ledger, "carl"));
+        assert_eq!(Coin::new(400, "usei"), app.wrap().query_balance("alice", "usei").unwrap());
+        let receipt: TransferReceipt = from_json(res.data.unwrap()).unwrap();
+        assert_eq!(Uint128::from(400u32), receipt.refund);
+        let stored: TransferReceipt = app.wrap().query_wasm_smart(&ledger, &QueryMsg::Receipt { id: receipt.transfer_id }).unwrap();
+        assert_eq!(receipt, stored);
+
+        let tip_msg = ExecuteMsg::TransferUsdWithTip {
+            address1: Addr::unchecked("bob"),
+            address2: Addr::unchecked("carl"),
+            usd_amount: Uint128::from(100u32),
+            belief_price: Decimal::percent(50),
+            max_slippage: Decimal::percent(1),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &tip_msg, &coins(400, "usei"))
+            .expect("alice pays 100 micro-USD with a tip");
+        assert_eq!(Uint128::from(10u32), ledger_balance(&app, &ledger, "owner"));
+        assert_eq!(Coin::new(190, "usei"), app.wrap().query_balance("alice", "usei").unwrap());
+    }
+
+    #[test]
+    fn transfer_usd_rejects_stale_or_slipped_price() {
+        let mut app = mock_app();
+        let (ledger, _oracle) = setup_oracle(&mut app);
+
+        // The caller expected a noticeably better price.
+        let err = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_usd_msg(300, Decimal::percent(60)), &coins(1000, "usei"))
+            .unwrap_err();
+        assert!(err.root_cause().to_string().contains("slippage"));
+
+        app.update_block(|block| block.time = block.time.plus_seconds(61));
+        let err = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_usd_msg(300, Decimal::percent(50)), &coins(1000, "usei"))
+            .unwrap_err();
+        assert!(err.root_cause().to_string().contains("older than 60 seconds"));
+        assert_eq!(Coin::new(1000, "usei"), app.wrap().query_balance("alice", "usei").unwrap());
+    }
+
+    // Staking pays 10% a year on usei, with a 60 second unbonding time, through two commission-free validators.
+    fn staking_app() -> App {
+        AppBuilder::new().build(|router, api, storage| {
+            router.bank.init_balance(storage, &Addr::unchecked("alice"), coins(1000, "usei")).unwrap();
+            router.bank.init_balance(storage, &Addr::unchecked("bank"), coins(10000, "uusdc")).unwrap();
+            router.staking.setup(storage, StakingInfo { bonded_denom: "usei".to_string(), unbonding_time: 60, apr: Decimal::percent(10) }).unwrap();
+            for address in ["validator1", "validator2"] {
+                let validator = Validator {
+                    address: address.to_string(),
+                    commission: Decimal::zero(),
+                    max_commission: Decimal::one(),
+                    max_change_rate: Decimal::one(),
+                };
+                router.staking.add_validator(api, storage, &cosmwasm_std::testing::mock_env().block, validator).unwrap();
+            }
+        })
+    }
+
+    // Splits alice's 1000 usei between bob and carl, then stakes everything but a 20% buffer.
+    fn setup_staking(app: &mut App) -> Addr {
+        let (ledger, _pair) = setup(app);
+        let transfer_msg = ExecuteMsg::Transfer {
+            address1: Addr::unchecked("bob"),
+            address2: Addr::unchecked("carl"),
+            amount: Uint128::from(1000u32),
+            memo: None,
+            reference: None,
+            idempotency_key: None,
+        };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(1000, "usei")).unwrap();
+
+        let staking_msg = ExecuteMsg::UpdateStaking {
+            validators: vec!["validator1".to_string(), "validator2".to_string()],
+            buffer_ratio: Decimal::percent(20),
+            unbonding_period: 60,
+        };
+        app.execute_contract(Addr::unchecked("owner"), ledger.clone(), &staking_msg, &[]).unwrap();
+        app.execute_contract(Addr::unchecked("carl"), ledger.clone(), &ExecuteMsg::Rebalance {}, &[]).unwrap();
+        ledger
+    }
+
+    fn delegations(app: &App, ledger: &Addr) -> Vec<(String, u128)> {
+        let res: StakingStateResponse = app.wrap().query_wasm_smart(ledger, &QueryMsg::StakingState {}).unwrap();
+        res.delegations.into_iter().map(|DelegationResponse { validator, amount }| (validator, amount.u128())).collect()
+    }
+
+    #[test]
+    fn rebalance_stakes_surplus_and_shares_rewards() {
+        let mut app = staking_app();
+        let ledger = setup_staking(&mut app);
+
+        assert_eq!(vec![("validator1".to_string(), 400), ("validator2".to_string(), 400)], delegations(&app, &ledger));
+        assert_eq!(Coin::new(200, "usei"), app.wrap().query_balance(&ledger, "usei").unwrap());
+
+        // A year at 10% earns 80 usei on the 800 staked, shared pro rata.
+        app.update_block(|block| block.time = block.time.plus_seconds(365 * 24 * 60 * 60));
+        app.execute_contract(Addr::unchecked("carl"), ledger.clone(), &ExecuteMsg::Rebalance {}, &[]).unwrap();
+
+        assert_eq!(Uint128::from(540u32), ledger_balance(&app, &ledger, "bob"));
+        assert_eq!(Uint128::from(540u32), ledger_balance(&app, &ledger, "carl"));
+        // 80% of the grown ledger is staked again.
+        assert_eq!(vec![("validator1".to_string(), 432), ("validator2".to_string(), 432)], delegations(&app, &ledger));
+
+        app.execute_contract(Addr::unchecked("bob"), ledger.clone(), &ExecuteMsg::Withdraw { amount: Uint128::from(200u32) }, &[])
+            .expect("bob withdraws from the buffer");
+        assert_eq!(Coin::new(200, "usei"), app.wrap().query_balance("bob", "usei").unwrap());
+        assert_eq!(Uint128::from(340u32), ledger_balance(&app, &ledger, "bob"));
+    }
+
+    #[test]
+    fn withdraw_beyond_buffer_unbonds() {
+        let mut app = staking_app();
+        let ledger = setup_staking(&mut app);
+
+        // Only 200 usei are liquid, the other 300 are undelegated.
+        app.execute_contract(Addr::unchecked("bob"), ledger.clone(), &ExecuteMsg::Withdraw { amount: Uint128::from(500u32) }, &[])
+            .expect("bob withdraws everything");
+        assert_eq!(Coin::new(200, "usei"), app.wrap().query_balance("bob", "usei").unwrap());
+        assert_eq!(Uint128::zero(), ledger_balance(&app, &ledger, "bob"));
+        assert_eq!(vec![("validator1".to_string(), 100), ("validator2".to_string(), 400)], delegations(&app, &ledger));
+
+        let err = app.execute_contract(Addr::unchecked("bob"), ledger.clone(), &ExecuteMsg::ClaimWithdrawal {}, &[]).unwrap_err();
+        assert!(err.root_cause().to_string().contains("No unbonded withdrawals"));
+
+        // Nothing is liquid until the next rebalance.
+        let err = app.execute_contract(Addr::unchecked("carl"), ledger.clone(), &ExecuteMsg::WithdrawAndSwap {
+            amount: Uint128::from(10u32),
+            denom: "usei".to_string(),
+            ask_denom: "uusdc".to_string(),
+            min_out: Uint128::zero(),
+        }, &[]).unwrap_err();
+        assert!(err.root_cause().to_string().contains("Not enough liquid usei"));
+
+        app.update_block(|block| block.time = block.time.plus_seconds(60));
+        app.update_block(|block| block.height += 1);
+        app.execute_contract(Addr::unchecked("bob"), ledger.clone(), &ExecuteMsg::ClaimWithdrawal {}, &[])
+            .expect("bob claims the unbonded usei");
+        assert_eq!(Coin::new(500, "usei"), app.wrap().query_balance("bob", "usei").unwrap());
+    }
+
+    #[test]
+    fn withdraw_unbonding_after_rewards_shares_them() {
+        let mut app = staking_app();
+        let ledger = setup_staking(&mut app);
+
+        // A year at 10% accrues 40 usei on each validator's 400.
+        app.update_block(|block| block.time = block.time.plus_seconds(365 * 24 * 60 * 60));
+
+        // Unbonding from validator1 pays out its rewards, which are shared before bob's withdrawal is debited.
+        app.execute_contract(Addr::unchecked("bob"), ledger.clone(), &ExecuteMsg::Withdraw { amount: Uint128::from(500u32) }, &[])
+            .expect("bob withdraws 500 usei");
+        assert_eq!(Uint128::from(20u32), ledger_balance(&app, &ledger, "bob"));
+        assert_eq!(Uint128::from(520u32), ledger_balance(&app, &ledger, "carl"));
+        // The 240 liquid usei (including the rewards) are paid out, and the other 260 unbond.
+        assert_eq!(Coin::new(240, "usei"), app.wrap().query_balance("bob", "usei").unwrap());
+        assert_eq!(vec![("validator1".to_string(), 140), ("validator2".to_string(), 400)], delegations(&app, &ledger));
+        assert_eq!(Coin::new(0, "usei"), app.wrap().query_balance(&ledger, "usei").unwrap());
+
+        let res: StakingStateResponse = app.wrap().query_wasm_smart(&ledger, &QueryMsg::StakingState {}).unwrap();
+        assert_eq!(Uint128::from(540u32), res.total_balance);
+
+        // Bob's share of the rewards is journaled when his withdrawal settles them.
+        let history_msg = QueryMsg::AccountHistory { address: Addr::unchecked("bob"), start_after: None, limit: Some(2), order: None };
+        let res: AccountHistoryResponse = app.wrap().query_wasm_smart(&ledger, &history_msg).unwrap();
+        let entries = res.entries.into_iter().map(|e| (e.kind, e.amount.u128())).collect::<Vec<_>>();
+        assert_eq!(vec![(EntryKind::Withdraw, 500), (EntryKind::Reward, 20)], entries);
+    }
+
+    fn cw4_group() -> Box<dyn Contract<Empty>> {
+        Box::new(ContractWrapper::new(cw4_group::contract::execute, cw4_group::contract::instantiate, cw4_group::contract::query))
+    }
+
+    fn member(addr: &str, weight: u64) -> cw4::Member {
+        cw4::Member { addr: addr.to_string(), weight }
+    }
+
+    // Instantiates a cw4 group of dave, erin and fred weighted 1:2:3, with the ledger registered and tracking it.
+    fn setup_tracked_group(app: &mut App, ledger: &Addr) -> Addr {
+        let group_id = app.store_code(cw4_group());
+        let group_msg = cw4_group::msg::InstantiateMsg {
+            admin: Some("owner".to_string()),
+            members: vec![member("dave", 1), member("erin", 2), member("fred", 3)],
+        };
+        let group = app.instantiate_contract(group_id, Addr::unchecked("owner"), &group_msg, &[], "team", None).unwrap();
+
+        let track_msg = ExecuteMsg::TrackGroup { group_contract: group.clone() };
+        let err = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &track_msg, &[]).unwrap_err();
+        assert!(err.root_cause().to_string().contains("dropped its hook"));
+
+        let hook_msg = cw4_group::msg::ExecuteMsg::AddHook { addr: ledger.to_string() };
+        app.execute_contract(Addr::unchecked("owner"), group.clone(), &hook_msg, &[]).unwrap();
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &track_msg, &[]).expect("alice starts tracking the group");
+        group
+    }
+
+    #[test]
+    fn transfer_to_group_by_weight() {
+        let mut app = mock_app();
+        let (ledger, _pair) = setup(&mut app);
+        let group = setup_tracked_group(&mut app, &ledger);
+
+        app.update_block(|block| block.height += 1);
+        let snapshot_height = app.block_info().height;
+        let update_msg = cw4_group::msg::ExecuteMsg::UpdateMembers { remove: vec![], add: vec![member("dave", 3)] };
+        app.execute_contract(Addr::unchecked("owner"), group.clone(), &update_msg, &[]).unwrap();
+        app.update_block(|block| block.height += 1);
+
+        // 100 usei split 1:2:3 as of the snapshot, with the usei lost to rounding going to fred.
+        let transfer_msg = ExecuteMsg::TransferToGroup { group_contract: group.clone(), at_height: Some(snapshot_height), memo: None, reference: None };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(100, "usei")).unwrap();
+        assert_eq!(Uint128::from(16u32), ledger_balance(&app, &ledger, "dave"));
+        assert_eq!(Uint128::from(33u32), ledger_balance(&app, &ledger, "erin"));
+        assert_eq!(Uint128::from(51u32), ledger_balance(&app, &ledger, "fred"));
+
+        // 800 usei split 3:2:3 by the current weights.
+        let transfer_msg = ExecuteMsg::TransferToGroup { group_contract: group, at_height: None, memo: None, reference: None };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(800, "usei")).unwrap();
+        assert_eq!(Uint128::from(316u32), ledger_balance(&app, &ledger, "dave"));
+        assert_eq!(Uint128::from(233u32), ledger_balance(&app, &ledger, "erin"));
+        assert_eq!(Uint128::from(351u32), ledger_balance(&app, &ledger, "fred"));
+    }
+
+    #[test]
+    fn transfer_to_group_at_height_includes_removed_members() {
+        let mut app = mock_app();
+        let (ledger, _pair) = setup(&mut app);
+
+        // Heights from before the group was tracked are refused.
+        let tracked_at = app.block_info().height;
+        let group = setup_tracked_group(&mut app, &ledger);
+        let transfer_msg = ExecuteMsg::TransferToGroup { group_contract: group.clone(), at_height: Some(tracked_at), memo: None, reference: None };
+        let err = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(60, "usei")).unwrap_err();
+        assert!(err.root_cause().to_string().contains("only recorded after height"));
+
+        // Fred leaves after the snapshot height, but still receives his share as of that height.
+        app.update_block(|block| block.height += 1);
+        let snapshot_height = app.block_info().height;
+        let update_msg = cw4_group::msg::ExecuteMsg::UpdateMembers { remove: vec!["fred".to_string()], add: vec![] };
+        app.execute_contract(Addr::unchecked("owner"), group.clone(), &update_msg, &[]).unwrap();
+        app.update_block(|block| block.height += 1);
+
+        let transfer_msg = ExecuteMsg::TransferToGroup { group_contract: group.clone(), at_height: Some(snapshot_height), memo: None, reference: None };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(60, "usei")).unwrap();
+        assert_eq!(Uint128::from(10u32), ledger_balance(&app, &ledger, "dave"));
+        assert_eq!(Uint128::from(20u32), ledger_balance(&app, &ledger, "erin"));
+        assert_eq!(Uint128::from(30u32), ledger_balance(&app, &ledger, "fred"));
+
+        // Once the group drops the hook, its recorded history is no longer trusted.
+        let hook_msg = cw4_group::msg::ExecuteMsg::RemoveHook { addr: ledger.to_string() };
+        app.execute_contract(Addr::unchecked("owner"), group.clone(), &hook_msg, &[]).unwrap();
+        let err = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(60, "usei")).unwrap_err();
+        assert!(err.root_cause().to_string().contains("dropped its hook"));
+
+        // Swapping erin for gina keeps the total weight, but is still noticed after the hook is added back.
+        let update_msg = cw4_group::msg::ExecuteMsg::UpdateMembers { remove: vec!["erin".to_string()], add: vec![member("gina", 2)] };
+        app.execute_contract(Addr::unchecked("owner"), group.clone(), &update_msg, &[]).unwrap();
+        let hook_msg = cw4_group::msg::ExecuteMsg::AddHook { addr: ledger.to_string() };
+        app.execute_contract(Addr::unchecked("owner"), group.clone(), &hook_msg, &[]).unwrap();
+        let err = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(60, "usei")).unwrap_err();
+        assert!(err.root_cause().to_string().contains("dropped its hook"));
+
+        // Tracking it again starts the history over.
+        let track_msg = ExecuteMsg::TrackGroup { group_contract: group.clone() };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &track_msg, &[]).unwrap();
+        let err = app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(60, "usei")).unwrap_err();
+        assert!(err.root_cause().to_string().contains("only recorded after height"));
+        app.update_block(|block| block.height += 1);
+        let transfer_msg = ExecuteMsg::TransferToGroup { group_contract: group, at_height: Some(app.block_info().height), memo: None, reference: None };
+        app.execute_contract(Addr::unchecked("alice"), ledger.clone(), &transfer_msg, &coins(30, "usei")).unwrap();
+        assert_eq!(Uint128::from(20u32), ledger_balance(&app, &ledger, "dave"));
+        assert_eq!(Uint128::from(20u32), ledger_balance(&app, &ledger, "erin"));
+        assert_eq!(Uint128::from(20u32), ledger_balance(&app, &ledger, "gina"));
+    }
 }